@@ -1,38 +1,315 @@
-use fontdb::{Database, Query};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use fontdb::{Database, Family, Query, Source, Style, Weight, ID};
 use swash::FontRef;
 
-use super::{Error, Font};
+use super::{has_color_glyphs, Error, Font, SharedData, Synthesis};
 use anyhow::Result;
 
-pub struct Loader<'a> {
+pub struct Loader {
     database: Database,
-    backing: &'a mut Vec<u8>,
+    /// the contents of every font file that a loaded face came from, so that loading more faces
+    /// from the same file doesn't read it again
+    files: HashMap<PathBuf, SharedData>,
+    /// codepoints that no face has a glyph for, and whether a color face was looked for, so that
+    /// every face isn't parsed again each time one comes up. Adding fonts clears it.
+    uncovered: HashSet<(u32, bool)>,
+}
+
+/// The faces that were loaded from a single font file or blob, in the order they appear in the
+/// collection
+#[derive(Clone, Debug, Default)]
+pub struct Collection {
+    faces: Vec<ID>,
+}
+
+/// The face that best matched a query, which has a synthetic bold or oblique applied if the
+/// family didn't have a face with the weight or style that was asked for
+#[derive(Clone, Debug)]
+pub struct FontMatch {
+    pub font: Font,
+    /// whether the face has exactly the weight, stretch and style that were asked for
+    pub exact: bool,
+    /// the styles that had to be faked, which are already applied to `font`
+    pub synthesis: Synthesis,
+}
+
+/// The ways a face can be picked out of the fonts a `Loader` knows about
+#[derive(Copy, Clone, Debug)]
+pub enum Selector<'q> {
+    Query(&'q Query<'q>),
+    /// the regular face of the family with this name
+    Family(&'q str),
+    PostScriptName(&'q str),
+    /// the face at this index inside a font collection
+    Index(&'q Collection, usize),
+    /// the first face that has a glyph for this codepoint
+    Covering(u32),
+    /// the first face with color glyphs that has a glyph for this codepoint, for emoji
+    CoveringColor(u32),
 }
 
-impl<'a> Loader<'a> {
-    pub fn system(backing: &'a mut Vec<u8>) -> Self {
-        let mut database = Database::new();
-        database.load_system_fonts();
-        Self { database, backing }
+impl Collection {
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+}
+
+impl Loader {
+    /// A loader that doesn't know about any fonts yet
+    pub fn empty() -> Self {
+        Self {
+            database: Database::new(),
+            files: HashMap::new(),
+            uncovered: HashSet::new(),
+        }
+    }
+
+    pub fn system() -> Self {
+        let mut loader = Self::empty();
+        loader.database.load_system_fonts();
+        loader
+    }
+
+    pub fn from_files<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Result<Self> {
+        let mut loader = Self::empty();
+        for path in paths {
+            loader.add_file(path)?;
+        }
+        Ok(loader)
+    }
+
+    pub fn from_dirs<P: AsRef<Path>>(dirs: impl IntoIterator<Item = P>) -> Self {
+        let mut loader = Self::empty();
+        for dir in dirs {
+            loader.add_dir(dir);
+        }
+        loader
+    }
+
+    pub fn from_static(fonts: impl IntoIterator<Item = &'static [u8]>) -> Result<Self> {
+        let mut loader = Self::empty();
+        for font in fonts {
+            loader.add_static(font)?;
+        }
+        Ok(loader)
+    }
+
+    pub fn from_bytes(fonts: impl IntoIterator<Item = Vec<u8>>) -> Result<Self> {
+        let mut loader = Self::empty();
+        for font in fonts {
+            loader.add_bytes(font)?;
+        }
+        Ok(loader)
+    }
+
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> Result<Collection> {
+        let path = path.as_ref();
+        self.add_source(Source::File(path.to_owned()), format!("{}", path.display()))
+    }
+
+    /// Recursively loads every font file in `dir`, skipping any that can't be parsed
+    pub fn add_dir(&mut self, dir: impl AsRef<Path>) {
+        self.database.load_fonts_dir(dir);
+        self.uncovered.clear();
+    }
+
+    pub fn add_static(&mut self, font: &'static [u8]) -> Result<Collection> {
+        self.add_source(Source::Binary(Arc::new(font)), "static font data".into())
+    }
+
+    pub fn add_bytes(&mut self, font: Vec<u8>) -> Result<Collection> {
+        self.add_source(Source::Binary(Arc::new(font)), "font data".into())
+    }
+
+    fn add_source(&mut self, source: Source, name: String) -> Result<Collection> {
+        let faces = self.database.load_font_source(source).to_vec();
+        if faces.is_empty() {
+            Err(Error::CouldNotReadFont(name))?;
+        }
+        self.uncovered.clear();
+        Ok(Collection { faces })
     }
 
-    pub fn load_font(&mut self, query: &Query) -> Result<Font> {
+    pub fn load_font(&mut self, query: &Query) -> Result<FontMatch> {
+        let selector = Selector::Query(query);
         let id = self
+            .find_face(selector)
+            .ok_or_else(|| Error::CouldNotLoadFont(format!("{selector:?}")))?;
+        let font = self.load_id(id, selector)?;
+        let face = self
             .database
-            .query(query)
-            .ok_or_else(|| Error::CouldNotLoadFont(format!("{query:?}")))?;
+            .face(id)
+            .ok_or_else(|| Error::CouldNotLoadFont(format!("{selector:?}")))?;
+        let exact = face.weight == query.weight
+            && face.stretch == query.stretch
+            && face.style == query.style;
+        let synthesis = Synthesis {
+            bold: query.weight >= Weight::SEMIBOLD && face.weight < Weight::SEMIBOLD,
+            oblique: query.style != Style::Normal && face.style == Style::Normal,
+        };
+        Ok(FontMatch {
+            font: font.with_synthesis(synthesis),
+            exact,
+            synthesis,
+        })
+    }
 
-        let (range, face) = self
+    pub fn load_face(&mut self, selector: Selector) -> Result<Font> {
+        let id = self
+            .find_face(selector)
+            .ok_or_else(|| Error::CouldNotLoadFont(format!("{selector:?}")))?;
+        self.load_id(id, selector)
+    }
+
+    /// Loads the face with `id`, where `selector` is what found it, for error messages
+    fn load_id(&mut self, id: ID, selector: Selector) -> Result<Font> {
+        let (source, index) = self
             .database
-            .with_face_data(id, |font_data, face_index| {
-                let start = self.backing.len();
-                self.backing.extend_from_slice(font_data);
-                (start..start + font_data.len(), face_index as usize)
-            })
-            .ok_or_else(|| Error::CouldNotParseFont(format!("{query:?}")))?;
-        let font_ref = FontRef::from_index(&self.backing[range], face)
-            .ok_or_else(|| Error::CouldNotParseFont(format!("{query:?}")))?;
-
-        Ok(Font::new(font_ref))
+            .face_source(id)
+            .ok_or_else(|| Error::CouldNotLoadFont(format!("{selector:?}")))?;
+
+        let data = match source {
+            Source::Binary(data) | Source::SharedFile(_, data) => data,
+            Source::File(path) => match self.files.get(&path) {
+                Some(data) => data.clone(),
+                None => {
+                    let data: SharedData = Arc::new(
+                        std::fs::read(&path)
+                            .map_err(|_| Error::CouldNotReadFont(format!("{}", path.display())))?,
+                    );
+                    self.files.insert(path, data.clone());
+                    data
+                }
+            },
+        };
+
+        Ok(Font::new(data, index as usize)
+            .ok_or_else(|| Error::CouldNotParseFont(format!("{selector:?}")))?)
+    }
+
+    fn find_covering(&mut self, codepoint: u32, color: bool) -> Option<ID> {
+        if self.uncovered.contains(&(codepoint, color)) {
+            return None;
+        }
+        let found = self.database.faces().map(|face| face.id).find(|&id| {
+            self.database
+                .with_face_data(id, |data, index| {
+                    FontRef::from_index(data, index as usize).is_some_and(|font| {
+                        font.charmap().map(codepoint) != 0 && (!color || has_color_glyphs(font))
+                    })
+                })
+                .unwrap_or(false)
+        });
+        if found.is_none() {
+            self.uncovered.insert((codepoint, color));
+        }
+        found
+    }
+
+    fn find_face(&mut self, selector: Selector) -> Option<ID> {
+        match selector {
+            Selector::Query(query) => self.database.query(query),
+            Selector::Family(name) => self.database.query(&Query {
+                families: &[Family::Name(name)],
+                ..Query::default()
+            }),
+            Selector::PostScriptName(name) => self
+                .database
+                .faces()
+                .find(|face| face.post_script_name == name)
+                .map(|face| face.id),
+            Selector::Index(collection, index) => collection.faces.get(index).copied(),
+            Selector::Covering(codepoint) => self.find_covering(codepoint, false),
+            Selector::CoveringColor(codepoint) => self.find_covering(codepoint, true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TUFFY: &[u8] = include_bytes!("../../tests/fonts/Tuffy.ttf");
+    /// a collection of two faces
+    const TTC: &[u8] = include_bytes!("../../tests/fonts/TTC.ttc");
+    /// a private use character, which Tuffy doesn't have
+    const UNCOVERED: u32 = 0x10FFFD;
+
+    fn face(loader: &Loader, id: ID) -> &fontdb::FaceInfo {
+        loader.database.face(id).unwrap()
+    }
+
+    #[test]
+    fn static_and_owned_fonts_are_loaded() {
+        let mut loader = Loader::from_static([TUFFY]).unwrap();
+        assert!(loader.load_face(Selector::Family("Tuffy")).is_ok());
+        let mut loader = Loader::from_bytes([TUFFY.to_vec()]).unwrap();
+        assert!(loader.load_face(Selector::Family("Tuffy")).is_ok());
+        assert!(Loader::from_bytes([vec![0; 16]]).is_err());
+    }
+
+    #[test]
+    fn faces_are_selected_by_family_and_postscript_name() {
+        let mut loader = Loader::from_static([TUFFY, TTC]).unwrap();
+        let id = loader.find_face(Selector::Family("Tuffy")).unwrap();
+        assert!(face(&loader, id)
+            .families
+            .iter()
+            .any(|(name, _)| name == "Tuffy"));
+        let name = face(&loader, id).post_script_name.clone();
+        assert_eq!(loader.find_face(Selector::PostScriptName(&name)), Some(id));
+        assert_eq!(loader.find_face(Selector::Family("Not Tuffy")), None);
+        assert_eq!(loader.find_face(Selector::PostScriptName("NotTuffy")), None);
+    }
+
+    #[test]
+    fn faces_are_selected_by_index_in_their_collection() {
+        let mut loader = Loader::from_static([TUFFY]).unwrap();
+        let collection = loader.add_static(TTC).unwrap();
+        assert_eq!(collection.len(), 2);
+        for index in 0..collection.len() {
+            let id = loader
+                .find_face(Selector::Index(&collection, index))
+                .unwrap();
+            let (source, face_index) = loader.database.face_source(id).unwrap();
+            assert!(matches!(source, Source::Binary(_)));
+            assert_eq!(face_index as usize, index);
+            assert!(loader
+                .load_face(Selector::Index(&collection, index))
+                .is_ok());
+        }
+        assert!(loader.load_face(Selector::Index(&collection, 2)).is_err());
+    }
+
+    #[test]
+    fn uncovered_codepoints_are_only_searched_for_once() {
+        let mut loader = Loader::from_static([TUFFY]).unwrap();
+        let a = loader.find_covering('a' as u32, false).unwrap();
+        assert_eq!(loader.find_face(Selector::Covering('a' as u32)), Some(a));
+        assert!(loader.uncovered.is_empty());
+
+        assert_eq!(loader.find_covering(UNCOVERED, false), None);
+        assert!(loader.uncovered.contains(&(UNCOVERED, false)));
+        // a color face is looked for separately
+        assert!(!loader.uncovered.contains(&(UNCOVERED, true)));
+        assert_eq!(loader.find_covering(UNCOVERED, true), None);
+        assert!(loader.uncovered.contains(&(UNCOVERED, true)));
+    }
+
+    #[test]
+    fn adding_fonts_forgets_uncovered_codepoints() {
+        let mut loader = Loader::from_static([TUFFY]).unwrap();
+        assert_eq!(loader.find_covering(UNCOVERED, false), None);
+        loader.add_bytes(TTC.to_vec()).unwrap();
+        assert!(loader.uncovered.is_empty());
     }
 }
@@ -0,0 +1,101 @@
+use swash::{scale::ScaleContext, FontRef, NormalizedCoord, Tag};
+
+/// Measurements that apply to a whole font, in pixels at one point size. Offsets are measured
+/// upwards from the baseline, so underline offsets are usually negative, while the descent is
+/// the positive distance down to the bottom of the line.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FontMetrics {
+    /// the size of the em square in font units, which is what everything else was scaled from
+    pub units_per_em: u16,
+    /// the distance from the baseline up to the top of the line
+    pub ascent: f32,
+    /// the distance from the baseline down to the bottom of the line
+    pub descent: f32,
+    /// the gap between the bottom of one line and the top of the next
+    pub leading: f32,
+    /// the height of flat capital letters like H
+    pub cap_height: f32,
+    /// the height of flat lowercase letters like x
+    pub x_height: f32,
+    /// where the top of an underline goes
+    pub underline_offset: f32,
+    pub underline_thickness: f32,
+    /// where the top of a strikeout line goes
+    pub strikeout_offset: f32,
+    pub strikeout_thickness: f32,
+}
+
+const POST: Tag = swash::tag_from_bytes(b"post");
+const OS2: Tag = swash::tag_from_bytes(b"OS/2");
+
+impl FontMetrics {
+    /// `coords` are the normalized variation coordinates of the instance to measure
+    pub fn new(font: FontRef, coords: &[NormalizedCoord], point: f32) -> Self {
+        let unscaled = font.metrics(coords);
+        let scale = if unscaled.units_per_em == 0 {
+            0.
+        } else {
+            point / unscaled.units_per_em as f32
+        };
+        let metrics = unscaled.scale(point);
+        // swash only has one stroke size, so the separate thicknesses are read from the tables
+        let underline_thickness = read_i16(font, POST, 10)
+            .map(|size| size as f32 * scale)
+            .unwrap_or(metrics.stroke_size);
+        let strikeout_thickness = read_i16(font, OS2, 26)
+            .map(|size| size as f32 * scale)
+            .unwrap_or(metrics.stroke_size);
+        Self {
+            units_per_em: metrics.units_per_em,
+            ascent: metrics.ascent,
+            descent: metrics.descent,
+            leading: metrics.leading,
+            cap_height: or_glyph_height(metrics.cap_height, font, coords, 'H', point),
+            x_height: or_glyph_height(metrics.x_height, font, coords, 'x', point),
+            underline_offset: metrics.underline_offset,
+            underline_thickness,
+            strikeout_offset: metrics.strikeout_offset,
+            strikeout_thickness,
+        }
+    }
+
+    /// The distance from one baseline to the next
+    pub fn line_advance(&self) -> f32 {
+        self.ascent + self.descent + self.leading
+    }
+}
+
+/// Older fonts don't have cap or x heights, so they're measured from the top of a glyph instead
+fn or_glyph_height(
+    height: f32,
+    font: FontRef,
+    coords: &[NormalizedCoord],
+    ch: char,
+    point: f32,
+) -> f32 {
+    if height != 0. {
+        return height;
+    }
+    let glyph = font.charmap().map(ch);
+    if glyph == 0 {
+        return 0.;
+    }
+    let mut context = ScaleContext::new();
+    let mut scaler = context
+        .builder(font)
+        .size(point)
+        .normalized_coords(coords)
+        .build();
+    scaler
+        .scale_outline(glyph)
+        .map(|outline| outline.bounds().max.y)
+        .unwrap_or(0.)
+}
+
+/// Reads a big endian `i16` at `offset` in a table, if the font has that table and it's long
+/// enough
+fn read_i16(font: FontRef, table: Tag, offset: usize) -> Option<i16> {
+    let data = font.table(table)?;
+    let bytes = data.get(offset..offset + 2)?;
+    Some(i16::from_be_bytes([bytes[0], bytes[1]])).filter(|&value| value != 0)
+}
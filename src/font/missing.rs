@@ -0,0 +1,166 @@
+use swash::scale::image::{Content, Image};
+
+use super::{GlyphMode, LoadedGlyph};
+
+/// What to draw for a character that no font in the stack has a glyph for, or whose glyph couldn't
+/// be rendered
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MissingGlyph {
+    /// the font's own .notdef glyph, which is usually an empty box
+    #[default]
+    Notdef,
+    /// another character instead, like U+FFFD REPLACEMENT CHARACTER
+    Replacement(char),
+    /// a box with the hex digits of the missing codepoint drawn inside it
+    HexBox,
+    /// nothing, and the character takes up no space in the line
+    Skip,
+}
+
+/// 3x5 pixel digits for 0 to F, one row per `u8` with the leftmost pixel in bit 2
+const DIGITS: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b111, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b111, 0b100, 0b100, 0b100, 0b111],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b111, 0b100, 0b111],
+    [0b111, 0b100, 0b111, 0b100, 0b100],
+];
+
+/// Draws a box sitting on the baseline with the hex digits of `codepoint` in two rows, like
+/// `00 AD` over each other for U+00AD. Codepoints above U+FFFF get three columns of digits.
+pub fn hex_box(codepoint: char, point: f32) -> LoadedGlyph {
+    let (columns, scale) = hex_box_grid(codepoint, point);
+    let advance_width = hex_box_advance(codepoint, point);
+    let codepoint = codepoint as u32;
+    // a border and a gap around the digits, and a gap between them
+    let width = scale * (4 * columns + 3);
+    let height = scale * 15;
+    let mut data = vec![0u8; width * height];
+    let mut fill = |x: usize, y: usize, w: usize, h: usize| {
+        for row in data[y * width..(y + h) * width].chunks_exact_mut(width) {
+            row[x..x + w].fill(255);
+        }
+    };
+    fill(0, 0, width, scale);
+    fill(0, height - scale, width, scale);
+    fill(0, 0, scale, height);
+    fill(width - scale, 0, scale, height);
+    for index in 0..2 * columns {
+        let (column, row) = (index % columns, index / columns);
+        let shift = 4 * (2 * columns - 1 - index);
+        let digit = DIGITS[(codepoint >> shift) as usize & 0xF];
+        let x = scale * (2 + 4 * column);
+        let y = scale * (2 + 6 * row);
+        for (line, bits) in digit.iter().enumerate() {
+            for pixel in 0..3 {
+                if bits & (0b100 >> pixel) != 0 {
+                    fill(x + scale * pixel, y + scale * line, scale, scale);
+                }
+            }
+        }
+    }
+    let mut image = Image::new();
+    image.content = Content::Mask;
+    image.placement.left = scale as i32;
+    image.placement.top = height as i32;
+    image.placement.width = width as u32;
+    image.placement.height = height as u32;
+    image.data = data;
+    LoadedGlyph {
+        image,
+        advance_width,
+        mode: GlyphMode::Coverage,
+        outline: Vec::new(),
+    }
+}
+
+/// How far the box drawn by `hex_box` moves the pen, which leaves a gap on both sides of it
+pub fn hex_box_advance(codepoint: char, point: f32) -> f32 {
+    let (columns, scale) = hex_box_grid(codepoint, point);
+    // the box, and a gap as wide as its border on each side
+    (scale * (4 * columns + 3) + 2 * scale) as f32
+}
+
+/// The columns of digits in the box for `codepoint`, and the size of one pixel of the digits,
+/// which grows with the text so the box stays readable
+fn hex_box_grid(codepoint: char, point: f32) -> (usize, usize) {
+    let columns = if codepoint as u32 > 0xFFFF { 3 } else { 2 };
+    (columns, (point / 16.).round().max(1.) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the digits back out of a box drawn by `hex_box`, row by row
+    fn read_digits(glyph: &LoadedGlyph, columns: usize, scale: usize) -> Vec<usize> {
+        let width = glyph.image.placement.width as usize;
+        let pixel = |x: usize, y: usize| glyph.image.data[y * width + x] == 255;
+        (0..2 * columns)
+            .map(|index| {
+                let (column, row) = (index % columns, index / columns);
+                let (x, y) = (scale * (2 + 4 * column), scale * (2 + 6 * row));
+                let digit: Vec<u8> = (0..5)
+                    .map(|line| {
+                        (0..3)
+                            .filter(|&bit| pixel(x + scale * bit, y + scale * line))
+                            .map(|bit| 0b100 >> bit)
+                            .sum()
+                    })
+                    .collect();
+                DIGITS
+                    .iter()
+                    .position(|known| known[..] == digit[..])
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hex_box_digits_for_basic_plane() {
+        let glyph = hex_box('\u{00AD}', 16.);
+        assert_eq!(glyph.image.placement.width, 11);
+        assert_eq!(glyph.image.placement.height, 15);
+        assert_eq!(read_digits(&glyph, 2, 1), [0x0, 0x0, 0xA, 0xD]);
+    }
+
+    #[test]
+    fn hex_box_digits_for_supplementary_plane() {
+        let glyph = hex_box('\u{1F9A9}', 16.);
+        assert_eq!(glyph.image.placement.width, 15);
+        assert_eq!(read_digits(&glyph, 3, 1), [0x0, 0x1, 0xF, 0x9, 0xA, 0x9]);
+    }
+
+    #[test]
+    fn hex_box_advance_leaves_a_gap_on_both_sides() {
+        for (codepoint, point) in [('\u{AD}', 12.), ('\u{1F9A9}', 16.), ('\u{E000}', 40.)] {
+            let glyph = hex_box(codepoint, point);
+            let scale = glyph.image.placement.left as f32;
+            assert_eq!(glyph.advance_width, hex_box_advance(codepoint, point));
+            assert_eq!(
+                glyph.advance_width,
+                glyph.image.placement.width as f32 + 2. * scale
+            );
+        }
+    }
+
+    #[test]
+    fn hex_box_grows_with_the_text() {
+        let glyph = hex_box('\u{E000}', 32.);
+        assert_eq!(glyph.image.placement.width, 22);
+        assert_eq!(glyph.image.placement.height, 30);
+        assert_eq!(glyph.image.placement.top, 30);
+        assert_eq!(read_digits(&glyph, 2, 2), [0xE, 0x0, 0x0, 0x0]);
+    }
+}
@@ -1,72 +1,366 @@
 use core::fmt;
-use std::cell::OnceCell;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
 
 use anyhow::Result;
 use swash::{
-    scale::{image::Image, Render, ScaleContext, Scaler, Source, StrikeWith},
-    zeno::{Format, Vector},
-    Charmap, FontRef,
+    scale::{
+        image::{Content, Image},
+        Render, ScaleContext, Source, StrikeWith,
+    },
+    tag_from_bytes,
+    zeno::{Format, Transform, Vector},
+    CacheKey, FontRef, GlyphId, NormalizedCoord, Setting,
 };
 use thiserror::Error;
 
 mod loader;
+mod metrics;
+mod missing;
+mod outline;
+mod raster;
+mod sdf;
+mod stack;
+mod synthesis;
+
+pub use loader::{Collection, FontMatch, Loader, Selector};
+pub use metrics::FontMetrics;
+pub use missing::{hex_box, hex_box_advance, MissingGlyph};
+pub use outline::Segment;
+pub use raster::{Antialias, RasterOptions};
+pub use sdf::GlyphMode;
+pub use stack::{FontId, FontStack};
+pub use synthesis::Synthesis;
 
-pub use loader::Loader;
+/// The bytes of a whole font file, shared between every `Font` that was loaded from it
+pub type SharedData = Arc<dyn AsRef<[u8]> + Send + Sync>;
 
-pub struct Font<'a> {
-    inner: FontRef<'a>,
-    charmap: Charmap<'a>,
-    render: Render<'a>,
+/// A single face inside some shared font data. Cloning a `Font` is cheap and doesn't copy the
+/// underlying data.
+#[derive(Clone)]
+pub struct Font {
+    data: SharedData,
+    offset: u32,
+    key: CacheKey,
+    /// the variation axis settings that were asked for, like a `wght` of 700
+    variations: Arc<[Setting<f32>]>,
+    /// the settings in `variations` normalized for this font's axes, which is empty for the
+    /// default instance
+    coords: Arc<[NormalizedCoord]>,
+    synthesis: Synthesis,
+    /// OpenType features that all text in this font is shaped with, like `("tnum", 1)`
+    features: Arc<[Setting<u16>]>,
 }
 
 pub struct LoadedGlyph {
     pub image: Image,
     pub advance_width: f32,
+    /// how the image was made, which is `Coverage` for color glyphs even if a distance field was
+    /// asked for
+    pub mode: GlyphMode,
+    /// the outline that the raster pass fills the image in from, which is only kept for
+    /// `GlyphMode::Gpu`. The image of such a glyph has a placement but no data.
+    pub outline: Vec<Segment>,
 }
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
-    #[error("Swash couldn't render codepoint {0}")]
-    CouldNotRender(u32),
+    #[error("Swash couldn't render glyph {0}")]
+    CouldNotRender(GlyphId),
     #[error("Fontdb couldn't load font {0}")]
     CouldNotLoadFont(String),
     #[error("Fontdb couldn't parse font {0}")]
     CouldNotParseFont(String),
+    #[error("Fontdb couldn't read any faces from {0}")]
+    CouldNotReadFont(String),
+    #[error("A font stack can't have more than {0} fonts")]
+    TooManyFonts(usize),
 }
 
-impl<'a> Font<'a> {
-    pub fn new(inner: FontRef<'a>) -> Self {
-        Self {
-            inner,
-            charmap: inner.charmap(),
-            render: Render::new(&[
-                Source::ColorOutline(0),
-                Source::ColorBitmap(StrikeWith::BestFit),
-                Source::Outline,
-            ]),
+const SOURCES: &[Source] = &[
+    Source::ColorOutline(0),
+    Source::ColorBitmap(StrikeWith::BestFit),
+    Source::Outline,
+];
+
+impl Font {
+    /// Returns `None` if `data` isn't a font file or doesn't have a face at `index`
+    pub fn new(data: SharedData, index: usize) -> Option<Self> {
+        let font_ref = FontRef::from_index((*data).as_ref(), index)?;
+        let (offset, key) = (font_ref.offset, font_ref.key);
+        Some(Self {
+            data,
+            offset,
+            key,
+            variations: Arc::new([]),
+            coords: Arc::new([]),
+            synthesis: Synthesis::NONE,
+            features: Arc::new([]),
+        })
+    }
+
+    /// Moves the font along its variation axes, like `wght`, `wdth`, `opsz` and `slnt` or any
+    /// custom axes it has. Settings replace earlier ones for the same axis, axes that aren't set
+    /// keep their defaults, and axes that the font doesn't have are ignored.
+    pub fn with_variations<I>(mut self, settings: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Setting<f32>>,
+    {
+        let mut variations = self.variations.to_vec();
+        for setting in settings {
+            let setting = setting.into();
+            variations.retain(|existing| existing.tag != setting.tag);
+            variations.push(setting);
+        }
+        let coords: Vec<_> = self
+            .font_ref()
+            .variations()
+            .normalized_coords(variations.iter().copied())
+            .collect();
+        self.coords = if coords.iter().all(|&coord| coord == 0) {
+            Arc::new([])
+        } else {
+            coords.into()
+        };
+        self.variations = variations.into();
+        self
+    }
+
+    pub fn variations(&self) -> &[Setting<f32>] {
+        &self.variations
+    }
+
+    /// The normalized variation coordinates that glyphs are scaled and shaped with
+    pub fn coords(&self) -> &[NormalizedCoord] {
+        &self.coords
+    }
+
+    /// Tells apart different instances of the same variable font in the atlas. The default
+    /// instance is always 0.
+    pub fn instance_key(&self) -> u64 {
+        if self.coords.is_empty() {
+            return 0;
         }
+        let mut hasher = DefaultHasher::new();
+        self.coords.hash(&mut hasher);
+        hasher.finish()
     }
 
-    pub fn load_glyph(&mut self, codepoint: impl Into<u32>, point: f32) -> Result<LoadedGlyph> {
-        let codepoint = codepoint.into();
-        let id = self.charmap.map(codepoint);
+    /// Turns OpenType features on or off for all text shaped with this font, like `("smcp", 1)`
+    /// for small caps or `("liga", 0)` to turn off ligatures. Settings replace earlier ones for
+    /// the same feature. Features can also be set on spans of text, which win over these.
+    pub fn with_features<I>(mut self, settings: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Setting<u16>>,
+    {
+        let mut features = self.features.to_vec();
+        for setting in settings {
+            let setting = setting.into();
+            features.retain(|existing| existing.tag != setting.tag);
+            features.push(setting);
+        }
+        self.features = features.into();
+        self
+    }
+
+    pub fn features(&self) -> &[Setting<u16>] {
+        &self.features
+    }
+
+    /// Fakes a bold or oblique style when glyphs are rendered
+    pub fn with_synthesis(mut self, synthesis: Synthesis) -> Self {
+        self.synthesis = synthesis;
+        self
+    }
+
+    pub fn synthesis(&self) -> Synthesis {
+        self.synthesis
+    }
+
+    pub fn font_ref(&self) -> FontRef<'_> {
+        FontRef {
+            data: (*self.data).as_ref(),
+            offset: self.offset,
+            key: self.key,
+        }
+    }
+
+    /// Whether this font has a glyph for `codepoint` other than .notdef
+    pub fn covers(&self, codepoint: impl Into<u32>) -> bool {
+        self.font_ref().charmap().map(codepoint) != 0
+    }
+
+    /// Whether the font has color layers or color bitmaps, like an emoji font
+    pub fn has_color_glyphs(&self) -> bool {
+        has_color_glyphs(self.font_ref())
+    }
+
+    /// Renders a glyph with its origin `offset_x` pixels right of a whole pixel. Distance fields
+    /// ignore the offset, since they're sampled between pixels anyway.
+    pub fn load_glyph(
+        &self,
+        id: GlyphId,
+        point: f32,
+        mode: GlyphMode,
+        options: &RasterOptions,
+        offset_x: f32,
+    ) -> Result<LoadedGlyph> {
+        let font = self.font_ref();
+        let embolden = self.synthesis.embolden(point);
+        let advance_width = self.advance_width(id, point);
         let mut context = ScaleContext::new();
-        let mut scaler = context.builder(self.inner).hint(true).size(point).build();
-        let image = self
-            .render
-            .format(Format::Subpixel)
-            .offset(Vector::new(0., 0.))
+        if self.is_blank(&mut context, id) {
+            // glyphs like space have nothing to draw, so they only need an advance in the atlas
+            return Ok(LoadedGlyph {
+                image: Image::new(),
+                advance_width,
+                mode,
+                outline: Vec::new(),
+            });
+        }
+        if mode != GlyphMode::Coverage {
+            // distance fields are scaled, so hinting to the pixel grid of one size doesn't help
+            let hint = mode == GlyphMode::Gpu && options.hinting;
+            let mut scaler = context
+                .builder(font)
+                .hint(hint)
+                .size(point)
+                .normalized_coords(self.coords.iter())
+                .build();
+            // color glyphs can't be turned into a single field or filled with one color, so
+            // they're kept as they are
+            let is_color = scaler.scale_color_outline(id).is_some();
+            if let Some(mut outline) = scaler.scale_outline(id).filter(|_| !is_color) {
+                if embolden != 0. {
+                    outline.embolden(embolden, embolden);
+                }
+                if let Some(skew) = self.synthesis.skew() {
+                    outline.transform(&skew);
+                }
+                if mode == GlyphMode::Gpu {
+                    outline.transform(&Transform::translation(offset_x, 0.));
+                    let mut image = Image::new();
+                    image.placement = outline::placement(&outline);
+                    return Ok(LoadedGlyph {
+                        image,
+                        advance_width,
+                        mode,
+                        outline: outline::segments(&outline),
+                    });
+                }
+                if let Some(image) = sdf::render_field(&outline, mode) {
+                    return Ok(LoadedGlyph {
+                        image,
+                        advance_width,
+                        mode,
+                        outline: Vec::new(),
+                    });
+                }
+            }
+        }
+        let mut scaler = context
+            .builder(font)
+            .hint(options.hinting)
+            .size(point)
+            .normalized_coords(self.coords.iter())
+            .build();
+        let mut image = Render::new(SOURCES)
+            .format(Format::Alpha)
+            .offset(Vector::new(offset_x, 0.))
+            .embolden(embolden)
+            .transform(self.synthesis.skew())
             .render(&mut scaler, id)
-            .ok_or(Error::CouldNotRender(codepoint))?;
-        let advance_width = self.inner.glyph_metrics(&[]).scale(point).advance_width(id);
+            .ok_or(Error::CouldNotRender(id))?;
+        if options.antialias == Antialias::Mono && image.content == Content::Mask {
+            for coverage in &mut image.data {
+                *coverage = if *coverage >= 128 { 255 } else { 0 };
+            }
+        }
+        if image.content == Content::Color {
+            premultiply(&mut image.data);
+        }
         Ok(LoadedGlyph {
             image,
             advance_width,
+            mode: GlyphMode::Coverage,
+            outline: Vec::new(),
         })
     }
 
+    /// How far glyph `id` moves the pen, without any kerning. A synthetic bold makes every glyph
+    /// that moves the pen at all a little wider.
+    pub fn advance_width(&self, id: GlyphId, point: f32) -> f32 {
+        let advance_width = self
+            .font_ref()
+            .glyph_metrics(&self.coords)
+            .scale(point)
+            .advance_width(id);
+        if advance_width != 0. {
+            advance_width + self.synthesis.embolden(point)
+        } else {
+            0.
+        }
+    }
+
+    /// Whether glyph `id` has an outline with nothing in it, and no color or bitmap version that
+    /// could be drawn instead
+    fn is_blank(&self, context: &mut ScaleContext, id: GlyphId) -> bool {
+        let mut scaler = context
+            .builder(self.font_ref())
+            .normalized_coords(self.coords.iter())
+            .build();
+        !scaler.has_bitmaps()
+            && !scaler.has_color_bitmaps()
+            && scaler.scale_color_outline(id).is_none()
+            && scaler
+                .scale_outline(id)
+                .is_some_and(|outline| outline.points().is_empty())
+    }
+
+    pub fn metrics(&self, point: f32) -> FontMetrics {
+        FontMetrics::new(self.font_ref(), &self.coords, point)
+    }
+
+    /// The distance from one baseline to the next
     pub fn line_height(&self, point: f32) -> f32 {
-        self.inner.metrics(&[]).scale(point).leading
+        self.metrics(point).line_advance()
+    }
+}
+
+/// Whether `font` has any of the tables that color glyphs are stored in
+fn has_color_glyphs(font: FontRef) -> bool {
+    [b"COLR", b"CBDT", b"sbix"]
+        .iter()
+        .any(|tag| font.table(tag_from_bytes(tag)).is_some())
+}
+
+/// Multiplies the color channels of RGBA pixels by their alpha, which swash leaves straight. Color
+/// glyphs are stored premultiplied so that sampling between a glyph's edge and the transparent
+/// padding around it doesn't darken the edge.
+fn premultiply(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel[..3] {
+            *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+impl fmt::Debug for Font {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Font")
+            .field("offset", &self.offset)
+            .field("key", &self.key)
+            .field("variations", &self.variations)
+            .field("synthesis", &self.synthesis)
+            .field("features", &self.features)
+            .finish()
     }
 }
 
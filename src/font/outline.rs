@@ -0,0 +1,71 @@
+use swash::{
+    scale::outline::Outline,
+    zeno::{Command, PathData, Placement, Vector},
+};
+
+/// One piece of a glyph outline, in pixels with y pointing up. Moves aren't kept, since every
+/// segment starts at its own first point.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Segment {
+    Line(Vector, Vector),
+    /// a quadratic curve from the first point to the last, pulled towards the middle one
+    Quad(Vector, Vector, Vector),
+    /// a cubic curve from the first point to the last, pulled towards the middle two
+    Cubic(Vector, Vector, Vector, Vector),
+}
+
+/// Splits `outline` into segments, adding the lines that close each contour
+pub fn segments(outline: &Outline) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut start = Vector::ZERO;
+    let mut pen = Vector::ZERO;
+    for command in outline.path().commands() {
+        match command {
+            Command::MoveTo(point) => {
+                if pen != start {
+                    segments.push(Segment::Line(pen, start));
+                }
+                start = point;
+                pen = point;
+            }
+            Command::LineTo(point) => {
+                segments.push(Segment::Line(pen, point));
+                pen = point;
+            }
+            Command::QuadTo(control, point) => {
+                segments.push(Segment::Quad(pen, control, point));
+                pen = point;
+            }
+            Command::CurveTo(control1, control2, point) => {
+                segments.push(Segment::Cubic(pen, control1, control2, point));
+                pen = point;
+            }
+            Command::Close => {
+                if pen != start {
+                    segments.push(Segment::Line(pen, start));
+                }
+                pen = start;
+            }
+        }
+    }
+    if pen != start {
+        segments.push(Segment::Line(pen, start));
+    }
+    segments
+}
+
+/// The smallest rectangle of whole pixels that covers `outline`
+pub fn placement(outline: &Outline) -> Placement {
+    let bounds = outline.bounds();
+    if bounds.min.x >= bounds.max.x || bounds.min.y >= bounds.max.y {
+        return Placement::default();
+    }
+    let (left, right) = (bounds.min.x.floor(), bounds.max.x.ceil());
+    let (bottom, top) = (bounds.min.y.floor(), bounds.max.y.ceil());
+    Placement {
+        left: left as i32,
+        top: top as i32,
+        width: (right - left) as u32,
+        height: (top - bottom) as u32,
+    }
+}
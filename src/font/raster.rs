@@ -0,0 +1,76 @@
+/// How glyph images are antialiased
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Antialias {
+    /// one channel of smooth coverage, which goes in the mask atlas
+    #[default]
+    Grayscale,
+    /// no antialiasing, so every pixel is either fully covered or not at all. Pixels that are at
+    /// least half covered are filled in, which gives aliased text for when the alpha is tested
+    /// instead of blended.
+    Mono,
+}
+
+/// Options for how coverage glyphs are rasterized
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RasterOptions {
+    /// snaps outlines to the pixel grid, which is sharper at small sizes but changes their shapes
+    pub hinting: bool,
+    pub antialias: Antialias,
+    /// how many horizontal positions inside a pixel glyphs are rasterized at. With 1, every glyph
+    /// starts on a whole pixel, which makes spacing uneven at small sizes.
+    pub subpixel_bins: u8,
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        Self {
+            hinting: true,
+            antialias: Antialias::Grayscale,
+            subpixel_bins: 1,
+        }
+    }
+}
+
+impl RasterOptions {
+    /// The bin for a glyph whose pen position has the fractional part `fract`
+    pub fn bin(&self, fract: f32) -> u8 {
+        let bins = self.subpixel_bins.max(1);
+        ((fract.rem_euclid(1.) * bins as f32) as u8).min(bins - 1)
+    }
+
+    /// How far right of a whole pixel the glyphs in `bin` are rasterized
+    pub fn bin_offset(&self, bin: u8) -> f32 {
+        bin as f32 / self.subpixel_bins.max(1) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::font::{Font, GlyphMode};
+
+    const TUFFY: &[u8] = include_bytes!("../../tests/fonts/Tuffy.ttf");
+
+    #[test]
+    fn mono_coverage_is_on_or_off() {
+        let font = Font::new(Arc::new(TUFFY), 0).unwrap();
+        let glyph_id = font.font_ref().charmap().map('O');
+        let coverage = |antialias| {
+            let options = RasterOptions {
+                antialias,
+                ..RasterOptions::default()
+            };
+            font.load_glyph(glyph_id, 16., GlyphMode::Coverage, &options, 0.)
+                .unwrap()
+                .image
+                .data
+        };
+        let partial = |data: &[u8]| data.iter().any(|&value| value != 0 && value != 255);
+        assert!(partial(&coverage(Antialias::Grayscale)));
+        let mono = coverage(Antialias::Mono);
+        assert!(!partial(&mono));
+        assert!(mono.contains(&255));
+    }
+}
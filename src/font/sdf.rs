@@ -0,0 +1,540 @@
+use swash::{
+    scale::{
+        image::{Content, Image},
+        outline::Outline,
+    },
+    zeno::{Bounds, Command, PathData, Placement, Vector},
+};
+
+/// How glyphs are turned into atlas images
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GlyphMode {
+    /// coverage, which only looks right when drawn at the size it was rasterized at
+    #[default]
+    Coverage,
+    /// a single channel signed distance field, which stays crisp when scaled up but rounds corners
+    Sdf {
+        /// how many pixels the field extends out from the outline on each side
+        spread: u8,
+    },
+    /// a three channel signed distance field, which also keeps corners sharp
+    Msdf {
+        /// how many pixels the field extends out from the outline on each side
+        spread: u8,
+    },
+    /// coverage, but filled in on the GPU by the raster pass instead of by swash
+    Gpu,
+}
+
+impl GlyphMode {
+    /// The size that distance fields are rasterized at, which one field is scaled from to every
+    /// size it's drawn at
+    pub const FIELD_SIZE: f32 = 32.;
+
+    /// Whether glyphs are turned into distance fields, which are drawn at any size
+    pub fn is_field(self) -> bool {
+        matches!(self, Self::Sdf { .. } | Self::Msdf { .. })
+    }
+}
+
+const RED: u8 = 1;
+const GREEN: u8 = 2;
+const BLUE: u8 = 4;
+const WHITE: u8 = RED | GREEN | BLUE;
+/// the colors that edges between corners are given in turn, each of which shares one channel with
+/// the others, so that the channel that differs at a corner keeps it sharp
+const EDGE_COLORS: [u8; 3] = [RED | BLUE, RED | GREEN, GREEN | BLUE];
+
+/// the sine of the smallest angle between two segments that counts as a corner
+const CORNER_THRESHOLD: f32 = 0.14;
+/// how many lines each curve is split into
+const CURVE_STEPS: usize = 16;
+
+#[derive(Copy, Clone, Debug)]
+struct Segment {
+    a: Vector,
+    b: Vector,
+}
+
+/// Lines between two corners of a contour
+#[derive(Clone, Debug, Default)]
+struct Edge {
+    segments: Vec<Segment>,
+    /// the channels of a multi-channel field that this edge contributes to
+    channels: u8,
+}
+
+/// The distance from a point to an edge, and which side of the edge it's on
+#[derive(Copy, Clone, Debug)]
+struct EdgeDistance {
+    distance: f32,
+    /// the distance to the line through the closest end of the edge if the point is past that end,
+    /// which is what keeps corners sharp, otherwise the same as `distance`
+    pseudo_distance: f32,
+    inside: bool,
+    /// how close to parallel the edge is to the line from its closest point to the point, which
+    /// picks between two edges that are both closest at the corner they share
+    parallel: f32,
+}
+
+impl EdgeDistance {
+    const FAR: Self = Self {
+        distance: f32::INFINITY,
+        pseudo_distance: f32::INFINITY,
+        inside: false,
+        parallel: 1.,
+    };
+
+    fn is_closer_than(&self, other: &Self) -> bool {
+        if (self.distance - other.distance).abs() <= 1e-4 {
+            return self.parallel < other.parallel;
+        }
+        self.distance < other.distance
+    }
+}
+
+/// Builds a distance field image from `outline`. The outline is in pixels with y pointing up, the
+/// same as what swash's scaler returns.
+pub fn render_field(outline: &Outline, mode: GlyphMode) -> Option<Image> {
+    render_path(outline.path(), outline.bounds(), mode)
+}
+
+/// Builds a distance field image like `render_field`, from any path inside `bounds`
+fn render_path(path: impl PathData, bounds: Bounds, mode: GlyphMode) -> Option<Image> {
+    let (spread, channels) = match mode {
+        GlyphMode::Coverage | GlyphMode::Gpu => return None,
+        GlyphMode::Sdf { spread } => (spread, 1),
+        GlyphMode::Msdf { spread } => (spread, 4),
+    };
+    let spread = spread.max(1) as f32;
+    let mut contours = contours(path);
+    if contours.is_empty() {
+        return None;
+    }
+    let flip = signed_area(&contours) < 0.;
+    for contour in &mut contours {
+        color_edges(contour);
+    }
+    let edges: Vec<&Edge> = contours.iter().flatten().collect();
+
+    let left = bounds.min.x.floor() as i32 - spread as i32;
+    let top = bounds.max.y.ceil() as i32 + spread as i32;
+    let width = (bounds.max.x.ceil() - bounds.min.x.floor()) as u32 + 2 * spread as u32;
+    let height = (bounds.max.y.ceil() - bounds.min.y.floor()) as u32 + 2 * spread as u32;
+
+    let mut image = Image::new();
+    image.placement = Placement {
+        left,
+        top,
+        width,
+        height,
+    };
+    image.content = if channels == 1 {
+        Content::Mask
+    } else {
+        Content::Color
+    };
+    image.data.reserve((width * height * channels) as usize);
+    // distances are stored so that the outline is at 0.5 and `spread` pixels in or out is 1 or 0
+    let encode = |distance: f32| ((0.5 + distance / (2. * spread)).clamp(0., 1.) * 255.) as u8;
+    for y in 0..height {
+        for x in 0..width {
+            let point = Vector::new(left as f32 + x as f32 + 0.5, top as f32 - y as f32 - 0.5);
+            if channels == 1 {
+                let inside = winding(&contours, point) != 0;
+                let distance = edges
+                    .iter()
+                    .map(|edge| edge_distance(edge, point, flip).distance)
+                    .fold(f32::INFINITY, f32::min);
+                image
+                    .data
+                    .push(encode(if inside { distance } else { -distance }));
+                continue;
+            }
+            for channel in [RED, GREEN, BLUE] {
+                let closest = edges
+                    .iter()
+                    .filter(|edge| edge.channels & channel != 0)
+                    .map(|edge| edge_distance(edge, point, flip))
+                    .reduce(|a, b| if b.is_closer_than(&a) { b } else { a });
+                let distance = closest.map_or(-spread, |closest| {
+                    if closest.inside {
+                        closest.pseudo_distance
+                    } else {
+                        -closest.pseudo_distance
+                    }
+                });
+                image.data.push(encode(distance));
+            }
+            image.data.push(255);
+        }
+    }
+    Some(image)
+}
+
+/// Flattens a path into closed contours, each split into edges at its corners
+fn contours(path: impl PathData) -> Vec<Vec<Edge>> {
+    let mut contours: Vec<Vec<Edge>> = Vec::new();
+    let mut start = Vector::ZERO;
+    let mut pen = Vector::ZERO;
+    // the direction the previous command ended in, to find corners
+    let mut last_direction: Option<Vector> = None;
+    for command in path.commands() {
+        match command {
+            Command::MoveTo(point) => {
+                close(&mut contours, pen, start);
+                contours.push(Vec::new());
+                last_direction = None;
+                start = point;
+                pen = point;
+            }
+            Command::LineTo(point) => {
+                if point != pen {
+                    let direction = point - pen;
+                    push_points(
+                        &mut contours,
+                        &mut last_direction,
+                        &[pen, point],
+                        direction,
+                        direction,
+                    );
+                }
+                pen = point;
+            }
+            Command::QuadTo(control, point) => {
+                let points: Vec<Vector> = (0..=CURVE_STEPS)
+                    .map(|step| {
+                        let t = step as f32 / CURVE_STEPS as f32;
+                        pen * ((1. - t) * (1. - t))
+                            + control * (2. * t * (1. - t))
+                            + point * (t * t)
+                    })
+                    .collect();
+                push_points(
+                    &mut contours,
+                    &mut last_direction,
+                    &points,
+                    tangent(&[pen, control, point]),
+                    tangent(&[point, control, pen]) * -1.,
+                );
+                pen = point;
+            }
+            Command::CurveTo(control1, control2, point) => {
+                let points: Vec<Vector> = (0..=CURVE_STEPS)
+                    .map(|step| {
+                        let t = step as f32 / CURVE_STEPS as f32;
+                        let u = 1. - t;
+                        pen * (u * u * u)
+                            + control1 * (3. * u * u * t)
+                            + control2 * (3. * u * t * t)
+                            + point * (t * t * t)
+                    })
+                    .collect();
+                push_points(
+                    &mut contours,
+                    &mut last_direction,
+                    &points,
+                    tangent(&[pen, control1, control2, point]),
+                    tangent(&[point, control2, control1, pen]) * -1.,
+                );
+                pen = point;
+            }
+            Command::Close => {
+                close(&mut contours, pen, start);
+                pen = start;
+            }
+        }
+    }
+    close(&mut contours, pen, start);
+    contours.retain(|contour| !contour.is_empty());
+    for contour in &mut contours {
+        // the contour usually starts in the middle of an edge, which then wraps around to the end
+        if contour.len() > 1 {
+            let first = &contour[0];
+            let last = &contour[contour.len() - 1];
+            let (first_direction, last_direction) = (
+                first.segments[0].b - first.segments[0].a,
+                last.segments[last.segments.len() - 1].b - last.segments[last.segments.len() - 1].a,
+            );
+            if !is_corner(last_direction, first_direction) {
+                let first = contour.remove(0);
+                contour.last_mut().unwrap().segments.extend(first.segments);
+            }
+        }
+    }
+    contours
+}
+
+/// Adds lines through `points` to the current contour, starting a new edge if they turn a corner
+fn push_points(
+    contours: &mut [Vec<Edge>],
+    last_direction: &mut Option<Vector>,
+    points: &[Vector],
+    start_direction: Vector,
+    end_direction: Vector,
+) {
+    let Some(contour) = contours.last_mut() else {
+        return;
+    };
+    let corner = last_direction.is_none_or(|last| is_corner(last, start_direction));
+    if corner || contour.is_empty() {
+        contour.push(Edge::default());
+    }
+    let edge = contour.last_mut().unwrap();
+    for pair in points.windows(2) {
+        edge.segments.push(Segment {
+            a: pair[0],
+            b: pair[1],
+        });
+    }
+    *last_direction = Some(end_direction);
+}
+
+/// Adds a line back to the start of the current contour if it didn't already end there
+fn close(contours: &mut [Vec<Edge>], pen: Vector, start: Vector) {
+    let Some(contour) = contours.last_mut() else {
+        return;
+    };
+    if pen == start || contour.is_empty() {
+        return;
+    }
+    let segment = Segment { a: pen, b: start };
+    let last = contour.last_mut().unwrap();
+    let last_segment = last.segments[last.segments.len() - 1];
+    if is_corner(last_segment.b - last_segment.a, start - pen) {
+        contour.push(Edge {
+            segments: vec![segment],
+            channels: 0,
+        });
+    } else {
+        last.segments.push(segment);
+    }
+}
+
+/// The direction a curve leaves its first point in, skipping control points on top of it
+fn tangent(points: &[Vector]) -> Vector {
+    points[1..]
+        .iter()
+        .map(|&point| point - points[0])
+        .find(|direction| *direction != Vector::ZERO)
+        .unwrap_or(Vector::ZERO)
+}
+
+fn is_corner(a: Vector, b: Vector) -> bool {
+    let (a, b) = (a.normalize(), b.normalize());
+    a.dot(b) <= 0. || a.cross(b).abs() > CORNER_THRESHOLD
+}
+
+/// Gives every edge of a contour a color, so that neighbouring edges never have the same one
+fn color_edges(contour: &mut [Edge]) {
+    // a contour without corners, like an o, is the same in every channel
+    if contour.len() == 1 {
+        contour[0].channels = WHITE;
+        return;
+    }
+    let len = contour.len();
+    for (index, edge) in contour.iter_mut().enumerate() {
+        edge.channels = EDGE_COLORS[index % 3];
+    }
+    // the last edge touches the first, so it can't wrap around to the same color
+    if len % 3 == 1 {
+        contour[len - 1].channels = EDGE_COLORS[1];
+    }
+}
+
+/// Twice the area enclosed by the contours, which is negative if the outer contours go clockwise
+fn signed_area(contours: &[Vec<Edge>]) -> f32 {
+    contours
+        .iter()
+        .flatten()
+        .flat_map(|edge| &edge.segments)
+        .map(|segment| segment.a.cross(segment.b))
+        .sum()
+}
+
+/// The nonzero winding number of the contours around `point`
+fn winding(contours: &[Vec<Edge>], point: Vector) -> i32 {
+    let mut winding = 0;
+    for segment in contours.iter().flatten().flat_map(|edge| &edge.segments) {
+        let (a, b) = (segment.a, segment.b);
+        let side = (b - a).cross(point - a);
+        if a.y <= point.y {
+            if b.y > point.y && side > 0. {
+                winding += 1;
+            }
+        } else if b.y <= point.y && side < 0. {
+            winding -= 1;
+        }
+    }
+    winding
+}
+
+fn edge_distance(edge: &Edge, point: Vector, flip: bool) -> EdgeDistance {
+    let last = edge.segments.len() - 1;
+    let mut closest = EdgeDistance::FAR;
+    for (index, segment) in edge.segments.iter().enumerate() {
+        let direction = segment.b - segment.a;
+        let length = direction.dot(direction);
+        let t = if length > 0. {
+            (point - segment.a).dot(direction) / length
+        } else {
+            0.
+        };
+        let nearest = segment.a + direction * t.clamp(0., 1.);
+        let distance = point.distance_to(nearest);
+        let parallel = if (0. ..=1.).contains(&t) || distance == 0. {
+            0.
+        } else {
+            direction
+                .normalize()
+                .dot((point - nearest).normalize())
+                .abs()
+        };
+        let side = direction.cross(point - segment.a);
+        // outlines that go counter clockwise have their inside on the left
+        let inside = (side > 0.) != flip;
+        let extends = (index == 0 && t < 0.) || (index == last && t > 1.);
+        let pseudo_distance = if extends && length > 0. {
+            side.abs() / length.sqrt()
+        } else {
+            distance
+        };
+        let candidate = EdgeDistance {
+            distance,
+            pseudo_distance,
+            inside,
+            parallel,
+        };
+        if candidate.is_closer_than(&closest) {
+            closest = candidate;
+        }
+    }
+    closest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8 pixel square with its bottom left corner on the origin
+    fn square() -> Vec<Command> {
+        vec![
+            Command::MoveTo(Vector::new(0., 0.)),
+            Command::LineTo(Vector::new(8., 0.)),
+            Command::LineTo(Vector::new(8., 8.)),
+            Command::LineTo(Vector::new(0., 8.)),
+            Command::Close,
+        ]
+    }
+
+    fn render_square(mode: GlyphMode) -> Image {
+        let bounds = Bounds::new(Vector::new(0., 0.), Vector::new(8., 8.));
+        render_path(&square(), bounds, mode).unwrap()
+    }
+
+    /// The channels of the pixel whose center is at `x`, `y` in the square's coordinates
+    fn pixel(image: &Image, x: f32, y: f32) -> &[u8] {
+        let placement = image.placement;
+        let channels = image.data.len() / (placement.width * placement.height) as usize;
+        let column = (x - 0.5) as i32 - placement.left;
+        let row = placement.top - (y + 0.5) as i32;
+        let start = (row as usize * placement.width as usize + column as usize) * channels;
+        &image.data[start..start + channels]
+    }
+
+    #[test]
+    fn field_is_bigger_inside_than_outside() {
+        let image = render_square(GlyphMode::Sdf { spread: 4 });
+        assert_eq!(image.content, Content::Mask);
+        let placement = image.placement;
+        assert_eq!(
+            (
+                placement.left,
+                placement.top,
+                placement.width,
+                placement.height
+            ),
+            (-4, 12, 16, 16)
+        );
+        assert!(pixel(&image, 4.5, 3.5)[0] > 128);
+        assert!(pixel(&image, -2.5, 3.5)[0] < 128);
+        assert!(pixel(&image, 4.5, 10.5)[0] < 128);
+        // far enough out or in to be clamped
+        assert_eq!(pixel(&image, -3.5, -3.5)[0], 0);
+    }
+
+    #[test]
+    fn field_is_half_way_at_the_edge() {
+        let image = render_square(GlyphMode::Sdf { spread: 4 });
+        // half a pixel in and out of the left edge, which is 1/16 of the whole range either way
+        assert_eq!(pixel(&image, 0.5, 3.5)[0], (0.5625 * 255.) as u8);
+        assert_eq!(pixel(&image, -0.5, 3.5)[0], (0.4375 * 255.) as u8);
+        // the same on the other sides
+        assert_eq!(pixel(&image, 7.5, 3.5), pixel(&image, 0.5, 3.5));
+        assert_eq!(pixel(&image, 3.5, 8.5), pixel(&image, -0.5, 3.5));
+    }
+
+    #[test]
+    fn field_is_the_same_whichever_way_the_outline_goes() {
+        let mut reversed = square();
+        reversed[1..4].reverse();
+        let bounds = Bounds::new(Vector::new(0., 0.), Vector::new(8., 8.));
+        let image = render_path(&reversed, bounds, GlyphMode::Sdf { spread: 4 }).unwrap();
+        assert_eq!(image.data, render_square(GlyphMode::Sdf { spread: 4 }).data);
+    }
+
+    #[test]
+    fn neighbouring_edges_get_different_colors() {
+        let mut contours = contours(&square());
+        assert_eq!(contours.len(), 1);
+        let contour = &mut contours[0];
+        assert_eq!(contour.len(), 4);
+        color_edges(contour);
+        for (index, edge) in contour.iter().enumerate() {
+            let next = &contour[(index + 1) % contour.len()];
+            assert_eq!(edge.channels.count_ones(), 2);
+            // two channels each out of three, so neighbours share exactly one
+            assert_ne!(edge.channels, next.channels);
+        }
+    }
+
+    #[test]
+    fn contours_without_corners_are_white() {
+        let circle = vec![
+            Command::MoveTo(Vector::new(0., 4.)),
+            Command::QuadTo(Vector::new(0., 8.), Vector::new(4., 8.)),
+            Command::QuadTo(Vector::new(8., 8.), Vector::new(8., 4.)),
+            Command::QuadTo(Vector::new(8., 0.), Vector::new(4., 0.)),
+            Command::QuadTo(Vector::new(0., 0.), Vector::new(0., 4.)),
+            Command::Close,
+        ];
+        let mut contours = contours(&circle);
+        color_edges(&mut contours[0]);
+        assert_eq!(contours[0].len(), 1);
+        assert_eq!(contours[0][0].channels, WHITE);
+    }
+
+    #[test]
+    fn multi_channel_field_keeps_corners_sharp() {
+        let image = render_square(GlyphMode::Msdf { spread: 4 });
+        assert_eq!(image.content, Content::Color);
+        let median = |pixel: &[u8]| {
+            let mut channels = [pixel[0], pixel[1], pixel[2]];
+            channels.sort();
+            channels[1]
+        };
+        assert!(median(pixel(&image, 4.5, 3.5)) > 128);
+        assert!(median(pixel(&image, -2.5, 3.5)) < 128);
+        // beside a corner, the channels disagree but their median is still outside
+        let beside = pixel(&image, 8.5, 7.5);
+        assert!(beside[..3].iter().any(|&channel| channel > 128));
+        assert!(median(beside) < 128);
+        // diagonally out from the corner, the field is as far out as it is beside an edge, where a
+        // single channel field is further out and rounds the corner off
+        let edge = median(pixel(&image, 8.5, 3.5));
+        assert_eq!(median(pixel(&image, 8.5, 8.5)), edge);
+        let single = render_square(GlyphMode::Sdf { spread: 4 });
+        assert!(pixel(&single, 8.5, 8.5)[0] < edge);
+        assert_eq!(beside[3], 255);
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::Result;
+
+use super::{Error, Font, Loader, Selector};
+
+/// An index into the fonts of a `FontStack`
+pub type FontId = u16;
+
+/// An ordered list of fonts, where each codepoint is rendered with the first font that has a glyph
+/// for it
+pub struct FontStack {
+    fonts: Vec<Font>,
+    /// used to find more fonts when none of `fonts` cover a codepoint
+    fallback: Option<Loader>,
+}
+
+impl FontStack {
+    pub fn new(primary: Font) -> Self {
+        Self {
+            fonts: vec![primary],
+            fallback: None,
+        }
+    }
+
+    /// Search every face `loader` knows about when none of the fonts in the stack cover a codepoint
+    pub fn with_fallback(mut self, loader: Loader) -> Self {
+        self.fallback = Some(loader);
+        self
+    }
+
+    /// Adds a font to the end of the stack, failing if the stack already has a font for every id
+    pub fn push(&mut self, font: Font) -> Result<FontId> {
+        let id = FontId::try_from(self.fonts.len())
+            .map_err(|_| Error::TooManyFonts(FontId::MAX as usize + 1))?;
+        self.fonts.push(font);
+        Ok(id)
+    }
+
+    /// The id of the first font that `covers` is true for
+    fn position(&self, covers: impl Fn(&Font) -> bool) -> Option<FontId> {
+        let index = self.fonts.iter().position(covers)?;
+        // `push` never lets the stack have more fonts than there are ids
+        FontId::try_from(index).ok()
+    }
+
+    pub fn primary(&self) -> &Font {
+        &self.fonts[0]
+    }
+
+    pub fn font(&self, id: FontId) -> &Font {
+        &self.fonts[id as usize]
+    }
+
+    /// Finds the font that `codepoint` should be rendered with. If no font covers it, or the one
+    /// that does can't be added because the stack is full, the primary font is used, which will
+    /// render .notdef.
+    pub fn resolve(&mut self, codepoint: impl Into<u32>) -> FontId {
+        let codepoint = codepoint.into();
+        if let Some(id) = self.position(|font| font.covers(codepoint)) {
+            return id;
+        }
+        self.fallback
+            .as_mut()
+            .and_then(|loader| loader.load_face(Selector::Covering(codepoint)).ok())
+            .and_then(|font| self.push(font).ok())
+            .unwrap_or(0)
+    }
+
+    /// Finds a font with color glyphs for the emoji `codepoint`, falling back to `resolve` if
+    /// there isn't one, in which case the emoji is drawn like text
+    pub fn resolve_emoji(&mut self, codepoint: impl Into<u32>) -> FontId {
+        let codepoint = codepoint.into();
+        let covers = |font: &Font| font.has_color_glyphs() && font.covers(codepoint);
+        if let Some(id) = self.position(covers) {
+            return id;
+        }
+        self.fallback
+            .as_mut()
+            .and_then(|loader| loader.load_face(Selector::CoveringColor(codepoint)).ok())
+            .and_then(|font| self.push(font).ok())
+            .unwrap_or_else(|| self.resolve(codepoint))
+    }
+}
+
+impl From<Font> for FontStack {
+    fn from(primary: Font) -> Self {
+        Self::new(primary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_stack_refuses_more_fonts() {
+        let Ok(font) = Loader::system().load_face(Selector::Family("DejaVu Sans")) else {
+            eprintln!("skipping, DejaVu Sans isn't installed");
+            return;
+        };
+        let mut stack = FontStack::new(font.clone());
+        for id in 1..=FontId::MAX {
+            assert_eq!(stack.push(font.clone()).unwrap(), id);
+        }
+        let err = stack.push(font).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::TooManyFonts(65536))
+        ));
+        // everything still resolves to a font that's in the stack
+        assert_eq!(stack.resolve('a'), 0);
+    }
+}
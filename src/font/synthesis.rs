@@ -0,0 +1,36 @@
+use swash::zeno::{Angle, Transform};
+
+/// Styles that are faked when a family doesn't have a face for them
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Synthesis {
+    /// dilates outlines, and widens advances to make room
+    pub bold: bool,
+    /// slants outlines to the right
+    pub oblique: bool,
+}
+
+impl Synthesis {
+    pub const NONE: Self = Self {
+        bold: false,
+        oblique: false,
+    };
+    /// how much wider a synthetic bold makes glyphs, as a fraction of the point size
+    const BOLD_STRENGTH: f32 = 1. / 24.;
+    /// how far a synthetic oblique leans, in degrees
+    const OBLIQUE_ANGLE: f32 = 12.;
+
+    /// How many pixels wider outlines and advances get at `point`
+    pub fn embolden(&self, point: f32) -> f32 {
+        if self.bold {
+            point * Self::BOLD_STRENGTH
+        } else {
+            0.
+        }
+    }
+
+    /// The transform that slants outlines, whose y points up
+    pub fn skew(&self) -> Option<Transform> {
+        self.oblique
+            .then(|| Transform::skew(Angle::from_degrees(Self::OBLIQUE_ANGLE), Angle::ZERO))
+    }
+}
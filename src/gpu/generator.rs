@@ -2,9 +2,11 @@ use std::{iter, mem::size_of, num::NonZeroU64};
 
 use wgpu::{include_wgsl, util::DeviceExt};
 
+use crate::preproc::AtlasView;
+
 use super::{
     command::{Command, CommandList},
-    FontData, GpuGlyphData, LineSize, Text, Vertex,
+    AtlasSize, GlyphPosition, GpuGlyphData, LineSize, Text, Vertex,
 };
 
 #[repr(C)]
@@ -15,7 +17,8 @@ struct IndexData {
 }
 
 pub struct GenerationPass {
-    pub font_data: wgpu::Buffer,
+    /// a uniform holding the `AtlasSize`
+    pub atlas_size: wgpu::Buffer,
     pub num_indices: wgpu::Buffer,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
@@ -33,9 +36,10 @@ impl GenerationPass {
         text: &Text,
         glyph_data: &wgpu::Buffer,
         layout_buffer: &wgpu::Buffer,
+        atlas: AtlasView,
     ) -> Self {
         let visibility = wgpu::ShaderStages::COMPUTE;
-        // text, size, glyph data, layout, font data, vertex, index, num indices
+        // text, size, glyph data, layout, atlas size, vertex, index, num indices, positions
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Generation Pass Bind Group Layout"),
             entries: &[
@@ -87,10 +91,10 @@ impl GenerationPass {
                     binding: 4,
                     visibility,
                     ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
                         min_binding_size: Some(
-                            NonZeroU64::new(size_of::<FontData>() as u64).unwrap(),
+                            NonZeroU64::new(size_of::<AtlasSize>() as u64).unwrap(),
                         ),
                     },
                     count: None,
@@ -129,21 +133,34 @@ impl GenerationPass {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(
+                            NonZeroU64::new(size_of::<GlyphPosition>() as u64).unwrap(),
+                        ),
+                    },
+                    count: None,
+                },
             ],
         });
-        let font_data = FontData {
-            line_height: text.line_height,
+        let atlas_size = AtlasSize {
+            width: atlas.width as f32,
+            height: atlas.height as f32,
         };
-        let font_data = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Font Data"),
-            contents: bytemuck::cast_slice(&[font_data]),
-            usage: wgpu::BufferUsages::STORAGE,
+        let atlas_size = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Atlas Size"),
+            contents: bytemuck::cast_slice(&[atlas_size]),
+            usage: wgpu::BufferUsages::UNIFORM,
         });
+        // there's at least one glyph's worth, since a binding can't be empty
+        let glyphs = text.glyphs.max(1) as wgpu::BufferAddress;
         let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Vertex Buffer"),
-            size: (4 * text.lines * text.line_length * size_of::<Vertex>() as u32)
-                as wgpu::BufferAddress
-                * 1024,
+            size: 4 * glyphs * size_of::<Vertex>() as wgpu::BufferAddress,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::VERTEX,
@@ -151,9 +168,7 @@ impl GenerationPass {
         });
         let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Index Buffer"),
-            size: (6 * text.lines * text.line_length * size_of::<u32>() as u32)
-                as wgpu::BufferAddress
-                * 1024,
+            size: 6 * glyphs * size_of::<u32>() as wgpu::BufferAddress,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::INDEX,
@@ -193,7 +208,7 @@ impl GenerationPass {
                 },
                 wgpu::BindGroupEntry {
                     binding: 4,
-                    resource: font_data.as_entire_binding(),
+                    resource: atlas_size.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 5,
@@ -207,6 +222,10 @@ impl GenerationPass {
                     binding: 7,
                     resource: num_indices_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: text.positions.as_entire_binding(),
+                },
             ],
         });
         let shader_module = device.create_shader_module(include_wgsl!("shaders/generator.wgsl"));
@@ -224,7 +243,7 @@ impl GenerationPass {
         });
 
         Self {
-            font_data,
+            atlas_size,
             vertex_buffer,
             index_buffer,
             bind_group_layout,
@@ -257,10 +276,7 @@ impl GenerationPass {
         rx.receive().await.unwrap().unwrap();
         let data = buffer_slice.get_mapped_range();
 
-        let index_data: &[IndexData] = bytemuck::cast_slice(&*data);
-
-        dbg_m!(index_data);
-
+        let index_data: &[IndexData] = bytemuck::cast_slice(&data);
         index_data[0].indices
     }
 }
@@ -269,7 +285,7 @@ impl Command for GenerationPass {
     // step 1. generate the coordinates of the vertices of each of the two triangles of each glyph
     // step 2. use an atomic bump allocator to put the vertices and indices into respective buffers
     fn push_buffers(&self, device: &wgpu::Device, commands: &mut CommandList) {
-        let workgroups = (self.line_length / 64).max(1);
+        let workgroups = self.line_length.div_ceil(64).max(1);
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Generation Pass Encoder"),
         });
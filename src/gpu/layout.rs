@@ -4,7 +4,7 @@ use wgpu::include_wgsl;
 
 use super::{
     command::{Command, CommandList},
-    GpuGlyphData, LineSize, Text,
+    GlyphPosition, GpuGlyphData, LineSize, Text,
 };
 
 pub struct LayoutPass {
@@ -18,7 +18,7 @@ pub struct LayoutPass {
 impl LayoutPass {
     pub fn new(device: &wgpu::Device, text: &Text, glyph_data: &wgpu::Buffer) -> Self {
         let visibility = wgpu::ShaderStages::COMPUTE;
-        // text, size, glyph data, layout
+        // text, size, glyph data, layout, positions
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Layout Pass Bind Group Layout"),
             entries: &[
@@ -36,7 +36,7 @@ impl LayoutPass {
                     binding: 1,
                     visibility,
                     ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
                         has_dynamic_offset: false,
                         min_binding_size: Some(
                             NonZeroU64::new(size_of::<LineSize>() as u64).unwrap(),
@@ -66,6 +66,18 @@ impl LayoutPass {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(
+                            NonZeroU64::new(size_of::<GlyphPosition>() as u64).unwrap(),
+                        ),
+                    },
+                    count: None,
+                },
             ],
         });
         let layout_buffer = device.create_buffer(&wgpu::BufferDescriptor {
@@ -94,6 +106,10 @@ impl LayoutPass {
                     binding: 3,
                     resource: layout_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: text.positions.as_entire_binding(),
+                },
             ],
         });
         let shader_module = device.create_shader_module(include_wgsl!("shaders/layout.wgsl"));
@@ -122,8 +138,10 @@ impl LayoutPass {
 
 impl Command for LayoutPass {
     // step 1. create 2d buffer to store the starting position of each glyph
-    // step 2. run an inclusive prefix sum, taking `advance_width` from `glyph_data` as the elements to scan with
-    // step 3. pack it all into a compute shader
+    // step 2. run an inclusive prefix sum, taking the shaped `advance_x` from `positions` as the elements to scan with
+    // step 3. take the width of the line from the final value of the sum, and shift the line or
+    //         stretch its gaps to align it within `LineSize::width`
+    // step 4. pack it all into a compute shader
     fn push_buffers(&self, device: &wgpu::Device, commands: &mut CommandList) {
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Layout Pass Encoder"),
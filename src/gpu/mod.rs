@@ -2,8 +2,13 @@ pub mod command;
 pub mod generator;
 pub mod layout;
 pub mod publish;
+pub mod raster;
 pub mod render;
 
+use swash::zeno::Vector;
+
+use crate::font::Segment;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct GpuGlyphData {
@@ -15,12 +20,86 @@ pub struct GpuGlyphData {
     pub width: u32,
     /// the height of the glyph in the atlas texture
     pub height: u32,
-    /// god knows what this means
+    /// the layer of the atlas texture that the glyph is in
+    pub page: u32,
+    /// one of `MASK`, `COLOR`, `SDF` or `MSDF`, which also decides which atlas
+    /// texture the glyph is in
+    pub content: u32,
+    /// the pixels from the pen's x to the left edge of the glyph's image, from swash's `Placement`
     pub left: i32,
-    /// god knows what this means
+    /// the pixels from the baseline up to the top edge of the glyph's image, from swash's
+    /// `Placement`
     pub top: i32,
     /// how much to move the cursor forwards after printing the glyph
     pub advance_x: f32,
+    /// how many atlas pixels the field of a distance field glyph extends out from the outline,
+    /// which is 0 for every other glyph. It isn't scaled with the glyph, since the render pass
+    /// measures it against how fast the texture coordinates change on screen.
+    pub spread: f32,
+}
+
+impl GpuGlyphData {
+    /// coverage in the mask atlas, tinted with the text color
+    pub const MASK: u32 = 0;
+    /// a color image in the color atlas, drawn as is
+    pub const COLOR: u32 = 1;
+    /// a signed distance field in the mask atlas
+    pub const SDF: u32 = 2;
+    /// a multi-channel signed distance field in the color atlas
+    pub const MSDF: u32 = 3;
+}
+
+/// One piece of a glyph outline, in pixels relative to the glyph's origin with y pointing up
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuSegment {
+    /// one of `LINE`, `QUAD` or `CUBIC`
+    pub kind: u32,
+    _padding: u32,
+    /// the start point, the control points that the kind uses, and the end point last
+    pub points: [[f32; 2]; 4],
+}
+
+impl GpuSegment {
+    pub const LINE: u32 = 0;
+    pub const QUAD: u32 = 1;
+    pub const CUBIC: u32 = 2;
+}
+
+impl From<Segment> for GpuSegment {
+    fn from(segment: Segment) -> Self {
+        let point = |vector: Vector| [vector.x, vector.y];
+        let (kind, points) = match segment {
+            Segment::Line(a, b) => (Self::LINE, [point(a), point(b), [0.; 2], [0.; 2]]),
+            Segment::Quad(a, c, b) => (Self::QUAD, [point(a), point(c), point(b), [0.; 2]]),
+            Segment::Cubic(a, c1, c2, b) => {
+                (Self::CUBIC, [point(a), point(c1), point(c2), point(b)])
+            }
+        };
+        Self {
+            kind,
+            _padding: 0,
+            points,
+        }
+    }
+}
+
+/// A glyph for the raster pass to fill in
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuRasterJob {
+    /// the index of the glyph's first segment in the segment buffer
+    pub first_segment: u32,
+    pub segments: u32,
+    pub width: u32,
+    pub height: u32,
+    /// the pixel position of the top left of the image, relative to the glyph's origin
+    pub left: i32,
+    pub top: i32,
+    /// where the image starts in the coverage buffer, in bytes
+    pub offset: u32,
+    /// the bytes between the starts of two rows of the image in the coverage buffer
+    pub stride: u32,
 }
 
 #[repr(C)]
@@ -30,6 +109,39 @@ pub struct LineSize {
     pub start: u32,
     /// the length of the line
     pub length: u32,
+    /// which edge the layout pass puts the line against, one of the constants below
+    pub align: u32,
+    /// the width the line is aligned in
+    pub width: f32,
+    /// where the line's glyphs sit, measured down from the top of the text
+    pub baseline: f32,
+}
+
+impl LineSize {
+    pub const LEFT: u32 = 0;
+    pub const RIGHT: u32 = 1;
+    pub const CENTER: u32 = 2;
+    pub const JUSTIFY: u32 = 3;
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GlyphPosition {
+    /// how much to move the cursor forwards after printing the glyph, as decided by the shaper
+    pub advance_x: f32,
+    pub advance_y: f32,
+    /// how far to shift the glyph away from the cursor
+    pub offset_x: f32,
+    pub offset_y: f32,
+    /// how many times bigger than its atlas image the glyph is drawn
+    pub scale: f32,
+    /// `GlyphPosition::GAP` if the glyph is a space between words
+    pub flags: u32,
+}
+
+impl GlyphPosition {
+    /// the glyph is a space that justified lines stretch
+    pub const GAP: u32 = 1;
 }
 
 // TODO: implement more fine grained line length systems
@@ -38,16 +150,27 @@ pub struct Text {
     pub text: wgpu::Buffer,
     /// a buffer of LineSize's
     pub size: wgpu::Buffer,
+    /// a buffer of GlyphPosition's, one for each element of the text buffer
+    pub positions: wgpu::Buffer,
     lines: u32,
     line_length: u32,
-    line_height: f32,
     pub glyphs: u32,
 }
 
+/// The size of the atlas textures, which texture coordinates are divided by
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct FontData {
-    line_height: f32,
+pub struct AtlasSize {
+    width: f32,
+    height: f32,
+}
+
+/// The size of the texture the render pass draws to, which vertex positions are measured in
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Viewport {
+    width: f32,
+    height: f32,
 }
 
 #[repr(C)]
@@ -57,4 +180,31 @@ pub struct Vertex {
     y: f32,
     texture_x: f32,
     texture_y: f32,
+    page: u32,
+    content: u32,
+    spread: f32,
+    /// storage arrays round the vertex up to the alignment of its positions
+    _padding: f32,
+}
+
+/// Settings for how the render pass draws glyphs
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RenderParams {
+    /// the color that mask glyphs are tinted with, as straight (not premultiplied) rgba
+    pub text_color: [f32; 4],
+    /// how many screen pixels the edges of distance field glyphs fade out over
+    pub edge_softness: f32,
+    /// uniforms are rounded up to the alignment of the color
+    _padding: [f32; 3],
+}
+
+impl Default for RenderParams {
+    fn default() -> Self {
+        Self {
+            text_color: [1.; 4],
+            edge_softness: 1.,
+            _padding: [0.; 3],
+        }
+    }
 }
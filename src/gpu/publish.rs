@@ -1,78 +1,71 @@
-use std::mem::size_of;
+use std::{iter, mem::size_of};
 
-use image::RgbaImage;
+use swash::scale::image::Content;
 use wgpu::util::DeviceExt;
 
-use crate::preproc::{self, AtlasView};
+use crate::font::GlyphMode;
+use crate::preproc::{
+    self, Align, Atlas, AtlasGlyph, AtlasKind, AtlasUpdates, AtlasView, DirtyRect,
+};
 
-use super::{GpuGlyphData, LineSize, Text};
-pub fn create_atlas_texture(atlas: AtlasView, device: &wgpu::Device) -> wgpu::Texture {
+use super::{GlyphPosition, GpuGlyphData, LineSize, Text};
+pub fn create_atlas_texture(
+    atlas: AtlasView,
+    kind: AtlasKind,
+    device: &wgpu::Device,
+) -> wgpu::Texture {
     let size = wgpu::Extent3d {
         width: atlas.width,
         height: atlas.height,
-        depth_or_array_layers: 1,
+        // there are always at least two layers, since GL makes a texture with a single layer a
+        // plain 2D texture, which samples as empty through the array view the render pass uses
+        depth_or_array_layers: atlas.pages(kind).max(2),
     };
+    let format = atlas_format(kind);
     let texture_desc = wgpu::TextureDescriptor {
-        label: Some("Atlas Texture"),
+        label: Some(match kind {
+            AtlasKind::Mask => "Mask Atlas Texture",
+            AtlasKind::Color => "Color Atlas Texture",
+        }),
         size,
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8Unorm,
+        format,
+        // R8Unorm can't be a storage texture, so the atlas is only ever written by copies
         usage: wgpu::TextureUsages::COPY_DST
-            | wgpu::TextureUsages::STORAGE_BINDING
             | wgpu::TextureUsages::TEXTURE_BINDING
             | wgpu::TextureUsages::COPY_SRC,
-        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+        view_formats: &[format],
     };
     device.create_texture(&texture_desc)
 }
 
-pub fn write_atlas_texture(atlas: AtlasView, texture: &wgpu::Texture, queue: &wgpu::Queue) {
-    let bytes_per_pixel = 4 * size_of::<u8>() as u32;
-    let bytes_per_row = bytes_per_pixel * atlas.width;
+pub fn atlas_format(kind: AtlasKind) -> wgpu::TextureFormat {
+    match kind {
+        AtlasKind::Mask => wgpu::TextureFormat::R8Unorm,
+        AtlasKind::Color => wgpu::TextureFormat::Rgba8Unorm,
+    }
+}
+
+pub fn write_atlas_texture(
+    atlas: AtlasView,
+    kind: AtlasKind,
+    texture: &wgpu::Texture,
+    queue: &wgpu::Queue,
+) {
     let data_layout = wgpu::ImageDataLayout {
         offset: 0,
-        bytes_per_row: Some(bytes_per_row),
+        bytes_per_row: Some(kind.bytes_per_pixel() * atlas.width),
         rows_per_image: Some(atlas.height),
     };
-    let mut data = vec![0u8; (bytes_per_row * atlas.height) as usize];
-    let mut write_pixel = |x: u32, y: u32, color: [u8; 4]| {
-        let index = bytes_per_pixel * x + bytes_per_row * y;
-
-        for i in 0..3 {
-            data[index as usize + i] = color[i];
-        }
-    };
-    for glyph in atlas.entries.values() {
-        RgbaImage::from_raw(
-            glyph.glyph.image.placement.width,
-            glyph.glyph.image.placement.height,
-            glyph.glyph.image.data.clone(),
-        )
-        .unwrap()
-        .save(format!("Glyph{}.bmp", glyph.id))
-        .unwrap();
-        for (i, color) in glyph.glyph.image.data.chunks_exact(4).enumerate() {
-            let local_x = i as u32 % glyph.glyph.image.placement.width;
-            let local_y = i as u32 / glyph.glyph.image.placement.width;
-
-            let x = glyph.x + local_x;
-            let y = glyph.y + local_y;
-
-            write_pixel(x, y, color.try_into().unwrap());
-        }
-    }
-    RgbaImage::from_raw(atlas.width, atlas.height, data.clone())
-        .unwrap()
-        .save("atlas_inter.bmp")
-        .unwrap();
+    let data = atlas.pixels(kind, texture.depth_or_array_layers());
     queue.write_texture(texture.as_image_copy(), &data, data_layout, texture.size());
     queue.submit([]);
 }
 
 pub fn create_atlas_buffer(atlas: AtlasView, device: &wgpu::Device) -> wgpu::Buffer {
-    let size = (size_of::<GpuGlyphData>() * atlas.entries.len()) as wgpu::BufferAddress;
+    let size = (size_of::<GpuGlyphData>() * atlas.ids as usize) as wgpu::BufferAddress;
     let buffer_desc = wgpu::BufferDescriptor {
         label: Some("Atlas Buffer"),
         size,
@@ -86,34 +79,168 @@ pub fn create_atlas_buffer(atlas: AtlasView, device: &wgpu::Device) -> wgpu::Buf
 }
 
 pub fn write_atlas_buffer(atlas: AtlasView, buffer: &wgpu::Buffer, queue: &wgpu::Queue) {
-    let mut data = vec![GpuGlyphData::default(); atlas.entries.len()];
+    let mut data = vec![GpuGlyphData::default(); atlas.ids as usize];
     for glyph in atlas.entries.values() {
-        let placement = glyph.glyph.image.placement;
-        let glyph_data = GpuGlyphData {
-            texture_x: glyph.x,
-            texture_y: glyph.y,
-            width: placement.width,
-            height: placement.height,
-            left: placement.left,
-            top: placement.top,
-            advance_x: glyph.glyph.advance_width,
+        data[glyph.id as usize] = glyph_data(glyph);
+    }
+    queue.write_buffer(buffer, 0, bytemuck::cast_slice(&data));
+}
+
+fn glyph_data(glyph: &AtlasGlyph) -> GpuGlyphData {
+    let placement = glyph.glyph.image.placement;
+    GpuGlyphData {
+        texture_x: glyph.x,
+        texture_y: glyph.y,
+        width: placement.width,
+        height: placement.height,
+        page: glyph.page,
+        content: match (glyph.glyph.mode, glyph.glyph.image.content) {
+            (GlyphMode::Sdf { .. }, _) => GpuGlyphData::SDF,
+            (GlyphMode::Msdf { .. }, _) => GpuGlyphData::MSDF,
+            (_, Content::Mask) => GpuGlyphData::MASK,
+            // glyphs are only ever rasterized to one channel of coverage or to color
+            (_, Content::SubpixelMask | Content::Color) => GpuGlyphData::COLOR,
+        },
+        left: placement.left,
+        top: placement.top,
+        advance_x: glyph.glyph.advance_width,
+        spread: match glyph.glyph.mode {
+            GlyphMode::Sdf { spread } | GlyphMode::Msdf { spread } => spread as f32,
+            _ => 0.,
+        },
+    }
+}
+
+/// Uploads only the parts of the atlas that changed since the last sync: one texture write for
+/// each new glyph, and one buffer write for each changed glyph data slot. If the atlas grew more
+/// pages or ids than the textures and `buffer` have room for, they're replaced with bigger copies,
+/// and any bind groups that use them have to be created again.
+///
+/// Returns the updates that were uploaded, so that new glyphs with `GlyphMode::Gpu` can be handed
+/// to a `RasterPass`.
+pub fn sync_atlas(
+    atlas: &mut Atlas,
+    mask_texture: &mut wgpu::Texture,
+    color_texture: &mut wgpu::Texture,
+    buffer: &mut wgpu::Buffer,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> AtlasUpdates {
+    let view = atlas.as_atlas_view();
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Atlas Sync Encoder"),
+    });
+    for (kind, texture) in [
+        (AtlasKind::Mask, &mut *mask_texture),
+        (AtlasKind::Color, &mut *color_texture),
+    ] {
+        if texture.depth_or_array_layers() < view.pages(kind) {
+            let grown = create_atlas_texture(view, kind, device);
+            encoder.copy_texture_to_texture(
+                texture.as_image_copy(),
+                grown.as_image_copy(),
+                texture.size(),
+            );
+            *texture = grown;
+        }
+    }
+    let needed = (size_of::<GpuGlyphData>() * view.ids as usize) as wgpu::BufferAddress;
+    if buffer.size() < needed {
+        let grown = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Atlas Buffer"),
+            // leave room to grow so that adding a glyph doesn't replace the buffer every time
+            size: needed.next_power_of_two(),
+            usage: buffer.usage(),
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(buffer, 0, &grown, 0, buffer.size());
+        *buffer = grown;
+    }
+    queue.submit(iter::once(encoder.finish()));
+
+    let texture = |kind| match kind {
+        AtlasKind::Mask => &*mask_texture,
+        AtlasKind::Color => &*color_texture,
+    };
+    let updates = atlas.take_updates();
+    for rect in &updates.cleared {
+        let bytes_per_pixel = rect.kind.bytes_per_pixel();
+        let data = vec![0u8; (rect.width * rect.height * bytes_per_pixel) as usize];
+        write_atlas_rect(texture(rect.kind), queue, *rect, &data);
+    }
+    for &id in &updates.glyphs {
+        let offset = (id as usize * size_of::<GpuGlyphData>()) as wgpu::BufferAddress;
+        let Some(glyph) = atlas.glyph(id) else {
+            queue.write_buffer(buffer, offset, bytemuck::bytes_of(&GpuGlyphData::default()));
+            continue;
         };
-        data[glyph.id as usize] = glyph_data;
+        queue.write_buffer(buffer, offset, bytemuck::bytes_of(&glyph_data(glyph)));
+        if glyph.glyph.mode == GlyphMode::Gpu {
+            continue;
+        }
+        write_atlas_rect(
+            texture(glyph.kind()),
+            queue,
+            glyph.rect(),
+            &glyph.glyph.image.data,
+        );
     }
-    queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&data));
+    updates
 }
 
-pub fn publish_text(text: &preproc::Text, device: &wgpu::Device, queue: &wgpu::Queue) -> Text {
+fn write_atlas_rect(texture: &wgpu::Texture, queue: &wgpu::Queue, rect: DirtyRect, data: &[u8]) {
+    if rect.width == 0 || rect.height == 0 {
+        return;
+    }
+    let bytes_per_pixel = rect.kind.bytes_per_pixel() * size_of::<u8>() as u32;
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: rect.x,
+                y: rect.y,
+                z: rect.page,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(bytes_per_pixel * rect.width),
+            rows_per_image: Some(rect.height),
+        },
+        wgpu::Extent3d {
+            width: rect.width,
+            height: rect.height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+pub fn publish_text(text: &preproc::Text, device: &wgpu::Device, _queue: &wgpu::Queue) -> Text {
     let mut line_length = 0;
     let size_data: Vec<_> = text
         .lines
         .iter()
-        .map(|line| line.glyphs.len() as u32)
-        .scan(0, |state, length| {
+        .scan(0, |state, line| {
+            let length = line.glyphs.len() as u32;
             line_length = line_length.max(length);
             let start = *state;
             *state += length;
-            Some(LineSize { start, length })
+            let align = match line.align {
+                Align::Right => LineSize::RIGHT,
+                Align::Center => LineSize::CENTER,
+                Align::Justify => LineSize::JUSTIFY,
+                _ => LineSize::LEFT,
+            };
+            Some(LineSize {
+                start,
+                length,
+                align,
+                width: line.width,
+                baseline: line.baseline(),
+            })
         })
         .collect();
     let glyphs = size_data
@@ -125,8 +252,24 @@ pub fn publish_text(text: &preproc::Text, device: &wgpu::Device, queue: &wgpu::Q
         .lines
         .iter()
         .flat_map(|line| &line.glyphs)
-        .copied()
-        .map(Into::into)
+        .map(|glyph| glyph.id.into())
+        .collect();
+
+    let position_data: Vec<GlyphPosition> = text
+        .lines
+        .iter()
+        .flat_map(|line| &line.glyphs)
+        .map(|glyph| GlyphPosition {
+            advance_x: glyph.advance_x,
+            advance_y: glyph.advance_y,
+            offset_x: glyph.offset_x,
+            offset_y: glyph.offset_y,
+            scale: glyph.scale,
+            flags: match glyph.gap {
+                true => GlyphPosition::GAP,
+                false => 0,
+            },
+        })
         .collect();
 
     let size_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -141,12 +284,18 @@ pub fn publish_text(text: &preproc::Text, device: &wgpu::Device, queue: &wgpu::Q
         usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
     });
 
+    let position_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Position Buffer"),
+        contents: bytemuck::cast_slice(&position_data),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+
     Text {
         text: text_buffer,
         size: size_buffer,
+        positions: position_buffer,
         lines: text.lines.len() as u32,
         line_length,
-        line_height: text.line_height,
         glyphs,
     }
 }
@@ -0,0 +1,211 @@
+use std::{mem::size_of, num::NonZeroU64};
+
+use wgpu::{include_wgsl, util::DeviceExt};
+
+use crate::{
+    font::GlyphMode,
+    preproc::{AtlasGlyph, DirtyRect},
+};
+
+use super::{
+    command::{Command, CommandList},
+    GpuRasterJob, GpuSegment,
+};
+
+/// Fills in the images of glyphs loaded with `GlyphMode::Gpu` from their outlines. Storage
+/// textures can't be R8Unorm, so coverage is written into a buffer and then copied into the mask
+/// atlas texture.
+pub struct RasterPass<'t> {
+    pub segment_buffer: wgpu::Buffer,
+    pub job_buffer: wgpu::Buffer,
+    /// the coverage of every glyph, one byte per pixel, with rows padded for copying to a texture
+    pub coverage_buffer: wgpu::Buffer,
+    pub mask_texture: &'t wgpu::Texture,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    pub pipeline: wgpu::ComputePipeline,
+    /// each job along with where its image goes in the atlas
+    jobs: Vec<(GpuRasterJob, DirtyRect)>,
+}
+
+impl<'t> RasterPass<'t> {
+    pub fn new<'a>(
+        device: &wgpu::Device,
+        glyphs: impl IntoIterator<Item = &'a AtlasGlyph>,
+        mask_texture: &'t wgpu::Texture,
+    ) -> Self {
+        let mut segments: Vec<GpuSegment> = Vec::new();
+        let mut jobs = Vec::new();
+        let mut coverage_size = 0;
+        for glyph in glyphs {
+            let rect = glyph.rect();
+            if glyph.glyph.mode != GlyphMode::Gpu || rect.width == 0 || rect.height == 0 {
+                continue;
+            }
+            let placement = glyph.glyph.image.placement;
+            let stride = rect
+                .width
+                .next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+            let job = GpuRasterJob {
+                first_segment: segments.len() as u32,
+                segments: glyph.glyph.outline.len() as u32,
+                width: rect.width,
+                height: rect.height,
+                left: placement.left,
+                top: placement.top,
+                offset: coverage_size,
+                stride,
+            };
+            segments.extend(
+                glyph
+                    .glyph
+                    .outline
+                    .iter()
+                    .map(|&segment| GpuSegment::from(segment)),
+            );
+            coverage_size += stride * rect.height;
+            jobs.push((job, rect));
+        }
+
+        // bindings can't be empty, so there's always at least one element in each buffer
+        let segment_data = if segments.is_empty() {
+            vec![GpuSegment::default()]
+        } else {
+            segments
+        };
+        let mut job_data: Vec<GpuRasterJob> = jobs.iter().map(|(job, _)| *job).collect();
+        if job_data.is_empty() {
+            job_data.push(GpuRasterJob::default());
+        }
+        let segment_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Segment Buffer"),
+            contents: bytemuck::cast_slice(&segment_data),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let job_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Raster Job Buffer"),
+            contents: bytemuck::cast_slice(&job_data),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let coverage_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Coverage Buffer"),
+            size: coverage_size.max(size_of::<u32>() as u32) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let visibility = wgpu::ShaderStages::COMPUTE;
+        let storage_entry = |binding, read_only, size: usize| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: Some(NonZeroU64::new(size as u64).unwrap()),
+            },
+            count: None,
+        };
+        // segments, jobs, coverage
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Raster Pass Bind Group Layout"),
+            entries: &[
+                storage_entry(0, true, size_of::<GpuSegment>()),
+                storage_entry(1, true, size_of::<GpuRasterJob>()),
+                storage_entry(2, false, size_of::<u32>()),
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Raster Pass Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: segment_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: job_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: coverage_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let shader_module = device.create_shader_module(include_wgsl!("shaders/raster.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Raster Pass Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Raster Pass Compute Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: "main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        });
+
+        Self {
+            segment_buffer,
+            job_buffer,
+            coverage_buffer,
+            mask_texture,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            jobs,
+        }
+    }
+}
+
+impl Command for RasterPass<'_> {
+    // step 1. one workgroup per glyph works out the coverage of four pixels at a time from the
+    // segments that cross each of a few rows of samples
+    // step 2. copy each glyph's coverage into its space in the mask atlas
+    fn push_buffers(&self, device: &wgpu::Device, commands: &mut CommandList) {
+        if self.jobs.is_empty() {
+            return;
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Raster Pass Encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Raster Pass Compute Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.set_pipeline(&self.pipeline);
+            pass.dispatch_workgroups(self.jobs.len() as u32, 1, 1);
+        }
+        for (job, rect) in &self.jobs {
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: &self.coverage_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: job.offset as wgpu::BufferAddress,
+                        bytes_per_row: Some(job.stride),
+                        rows_per_image: Some(job.height),
+                    },
+                },
+                wgpu::ImageCopyTexture {
+                    texture: self.mask_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: rect.x,
+                        y: rect.y,
+                        z: rect.page,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::Extent3d {
+                    width: rect.width,
+                    height: rect.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        commands.push(encoder.finish());
+    }
+}
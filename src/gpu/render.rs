@@ -1,19 +1,25 @@
 use std::{iter, mem::size_of};
 
-use super::Vertex;
+use wgpu::util::DeviceExt;
+
+use super::{RenderParams, Vertex, Viewport};
 
 pub struct RenderPass<'a, 'g, 's> {
     pub surface: &'s wgpu::Texture,
     pub vertex_buffer: &'g wgpu::Buffer,
     pub index_buffer: &'g wgpu::Buffer,
-    pub atlas_texture: &'a wgpu::Texture,
+    pub mask_texture: &'a wgpu::Texture,
+    pub color_texture: &'a wgpu::Texture,
+    /// a uniform holding the `RenderParams`
+    pub params_buffer: wgpu::Buffer,
+    /// a uniform holding the `Viewport`
+    pub viewport_buffer: wgpu::Buffer,
     pub render_pipeline: wgpu::RenderPipeline,
     pub num_indices: u32,
     pub bind_group: wgpu::BindGroup,
 }
 
-const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 2] =
-    wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2];
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint32, 3 => Uint32, 4 => Float32];
 
 impl<'a, 'g, 's> RenderPass<'a, 'g, 's> {
     pub fn new(
@@ -21,32 +27,77 @@ impl<'a, 'g, 's> RenderPass<'a, 'g, 's> {
         surface: &'s wgpu::Texture,
         vertex_buffer: &'g wgpu::Buffer,
         index_buffer: &'g wgpu::Buffer,
-        atlas_texture: &'a wgpu::Texture,
+        mask_texture: &'a wgpu::Texture,
+        color_texture: &'a wgpu::Texture,
         num_indices: u32,
     ) -> Self {
         let visibility = wgpu::ShaderStages::FRAGMENT;
+        let atlas_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        };
+        // mask atlas, color atlas, sampler, render params, viewport
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Render Pass Bind Group Layout"),
             entries: &[
+                atlas_entry(0),
+                atlas_entry(1),
                 wgpu::BindGroupLayoutEntry {
-                    binding: 0,
+                    binding: 2,
                     visibility,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
                 wgpu::BindGroupLayoutEntry {
-                    binding: 1,
+                    binding: 3,
                     visibility,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
                     count: None,
                 },
             ],
         });
-        let atlas_view = atlas_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let array_view = |texture: &wgpu::Texture| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            })
+        };
+        let mask_view = array_view(mask_texture);
+        let color_view = array_view(color_texture);
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Render Params Buffer"),
+            contents: bytemuck::bytes_of(&RenderParams::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let viewport = Viewport {
+            width: surface.width() as f32,
+            height: surface.height() as f32,
+        };
+        let viewport_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Viewport Buffer"),
+            contents: bytemuck::bytes_of(&viewport),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
         let atlas_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -62,12 +113,24 @@ impl<'a, 'g, 's> RenderPass<'a, 'g, 's> {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&atlas_view),
+                    resource: wgpu::BindingResource::TextureView(&mask_view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&color_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
                     resource: wgpu::BindingResource::Sampler(&atlas_sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: viewport_buffer.as_entire_binding(),
+                },
             ],
         });
         let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/render.wgsl"));
@@ -96,10 +159,10 @@ impl<'a, 'g, 's> RenderPass<'a, 'g, 's> {
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 targets: &[Some(wgpu::ColorTargetState {
                     format: wgpu::TextureFormat::Rgba8Unorm,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
+                    // glyphs are drawn over each other and the background, so coverage has to blend.
+                    // The shader writes premultiplied color, so that color glyphs can be sampled
+                    // without dark fringes.
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
             }),
@@ -125,13 +188,20 @@ impl<'a, 'g, 's> RenderPass<'a, 'g, 's> {
         Self {
             vertex_buffer,
             index_buffer,
-            atlas_texture,
+            mask_texture,
+            color_texture,
+            params_buffer,
+            viewport_buffer,
             render_pipeline,
             surface,
             num_indices,
             bind_group,
         }
     }
+    pub fn set_params(&self, queue: &wgpu::Queue, params: &RenderParams) {
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(params));
+    }
+
     pub fn render(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
         let view = self
             .surface
@@ -165,7 +235,6 @@ impl<'a, 'g, 's> RenderPass<'a, 'g, 's> {
             pass.set_bind_group(0, &self.bind_group, &[]);
             pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            println!("calculated num indicies to be {}", self.num_indices);
             pass.draw_indexed(0..self.num_indices, 0, 0..1);
         }
         queue.submit(iter::once(encoder.finish()));
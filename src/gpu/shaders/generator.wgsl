@@ -1,6 +1,18 @@
 struct LineSize {
     start: u32,
-    length: u32
+    length: u32,
+    align: u32,
+    width: f32,
+    baseline: f32
+}
+
+struct GlyphPosition {
+    advance_x: f32,
+    advance_y: f32,
+    offset_x: f32,
+    offset_y: f32,
+    scale: f32,
+    flags: u32
 }
 
 struct GlyphData {
@@ -8,18 +20,25 @@ struct GlyphData {
     texture_y: u32,
     width: u32,
     height: u32,
+    page: u32,
+    content: u32,
     left: i32,
     top: i32,
-    advance_x: f32
+    advance_x: f32,
+    spread: f32
 }
 
-struct FontData {
-    line_height: f32
+struct AtlasSize {
+    width: f32,
+    height: f32
 }
 
 struct Vertex {
     position: vec2<f32>,
     texture_position: vec2<f32>,
+    page: u32,
+    content: u32,
+    spread: f32,
 }
 
 struct IndexData {
@@ -45,7 +64,7 @@ var<storage, read_write> layout_offset: array<f32>;
 
 @group(0)
 @binding(4)
-var<storage, read_write> font_data: FontData;
+var<uniform> atlas_size: AtlasSize;
 
 @group(0)
 @binding(5)
@@ -59,6 +78,13 @@ var<storage, read_write> index: array<u32>;
 @binding(7)
 var<storage, read_write> num_indices: IndexData;
 
+@group(0)
+@binding(8)
+var<storage, read_write> positions: array<GlyphPosition>;
+
+const SDF: u32 = 2;
+const MSDF: u32 = 3;
+
 fn allocate_vertex() -> u32 {
     return atomicAdd(&num_indices.next_vertex, 1u);
 }
@@ -70,35 +96,55 @@ fn allocate_triangle_indices() -> u32 {
 @compute
 @workgroup_size(64, 1)
 fn main(
-    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(workgroup_id) workgroup_id: vec3<u32>
 ) {
     let line_size = size[workgroup_id.y];
-    if (local_id.x >= line_size.length) {
+    if (global_id.x >= line_size.length) {
         return;
     }
 
     let start = line_size.start;
-    let text_id = start + local_id.x;
+    let text_id = start + global_id.x;
     let glyph_id = text[text_id];
     let glyph = glyph_data[glyph_id];
 
-    let offset = layout_offset[text_id];
-
-    let a = vec2<f32>(offset, 10);
-    let b = vec2<f32>(offset + 5, 10);
-    let c = vec2<f32>(offset, 15);
-    let d = vec2<f32>(offset + 5, 15);
-
-    let a_t = vec2<f32>(f32(glyph.texture_x), f32(glyph.texture_y));
-    let b_t = vec2<f32>(f32(glyph.texture_x), f32(glyph.texture_y) + f32(glyph.width));
-    let c_t = vec2<f32>(f32(glyph.texture_x) + f32(glyph.height), f32(glyph.texture_y));
-    let d_t = vec2<f32>(f32(glyph.texture_x) + f32(glyph.height), f32(glyph.texture_y) + f32(glyph.width));
-
-    let a_i = 4 * local_id.x;
-    let b_i = 4 * local_id.x + 1;
-    let c_i = 4 * local_id.x + 2;
-    let d_i = 4 * local_id.x + 3;
+    let position = positions[text_id];
+    // the shaper's offsets point up, while y points down the text
+    var pen = vec2<f32>(
+        layout_offset[text_id] + position.offset_x,
+        line_size.baseline - position.offset_y
+    );
+    // coverage and color images were rasterized on the pixel grid, with the fraction of the pen
+    // already in their subpixel bin, so they're snapped back onto it. Distance fields are drawn
+    // exactly where the pen is.
+    if (glyph.content != SDF && glyph.content != MSDF) {
+        pen = floor(pen);
+    }
+    let glyph_size = vec2<f32>(f32(glyph.width), f32(glyph.height));
+    // distance fields are stored at one size and scaled to the size they're drawn at, along with
+    // where their image sits around the pen
+    let quad_size = glyph_size * position.scale;
+    let top_left = pen + vec2<f32>(f32(glyph.left), -f32(glyph.top)) * position.scale;
+
+    let a = top_left;
+    let b = top_left + vec2<f32>(quad_size.x, 0.0);
+    let c = top_left + vec2<f32>(0.0, quad_size.y);
+    let d = top_left + quad_size;
+
+    // texture coordinates go from 0 to 1 across the atlas
+    let atlas = vec2<f32>(atlas_size.width, atlas_size.height);
+    let texture_top_left = vec2<f32>(f32(glyph.texture_x), f32(glyph.texture_y));
+    let a_t = texture_top_left / atlas;
+    let b_t = (texture_top_left + vec2<f32>(glyph_size.x, 0.0)) / atlas;
+    let c_t = (texture_top_left + vec2<f32>(0.0, glyph_size.y)) / atlas;
+    let d_t = (texture_top_left + glyph_size) / atlas;
+
+    // every glyph of the text gets its own four vertices and six indices, so lines don't overlap
+    let a_i = 4 * text_id;
+    let b_i = 4 * text_id + 1;
+    let c_i = 4 * text_id + 2;
+    let d_i = 4 * text_id + 3;
 
     vertex[a_i].position = a;
     vertex[a_i].texture_position = a_t;
@@ -108,9 +154,21 @@ fn main(
     vertex[c_i].texture_position = c_t;
     vertex[d_i].position = d;
     vertex[d_i].texture_position = d_t;
-
-    let first = local_id.x * 6;
-    let second = local_id.x * 6 + 3;
+    vertex[a_i].page = glyph.page;
+    vertex[b_i].page = glyph.page;
+    vertex[c_i].page = glyph.page;
+    vertex[d_i].page = glyph.page;
+    vertex[a_i].content = glyph.content;
+    vertex[b_i].content = glyph.content;
+    vertex[c_i].content = glyph.content;
+    vertex[d_i].content = glyph.content;
+    vertex[a_i].spread = glyph.spread;
+    vertex[b_i].spread = glyph.spread;
+    vertex[c_i].spread = glyph.spread;
+    vertex[d_i].spread = glyph.spread;
+
+    let first = text_id * 6;
+    let second = text_id * 6 + 3;
 
     index[first] = a_i;
     index[first + 1] = c_i;
@@ -1,6 +1,18 @@
 struct LineSize {
     start: u32,
-    length: u32
+    length: u32,
+    align: u32,
+    width: f32,
+    baseline: f32
+}
+
+struct GlyphPosition {
+    advance_x: f32,
+    advance_y: f32,
+    offset_x: f32,
+    offset_y: f32,
+    scale: f32,
+    flags: u32
 }
 
 struct GlyphData {
@@ -8,18 +20,22 @@ struct GlyphData {
     texture_y: u32,
     width: u32,
     height: u32,
+    page: u32,
+    content: u32,
     left: i32,
     top: i32,
-    advance_x: f32
+    advance_x: f32,
+    spread: f32
 }
 
 @group(0)
 @binding(0)
 var<storage, read_write> text: array<u32>;
 
+// read only, so that the loops over a line are uniform across the workgroup
 @group(0)
 @binding(1)
-var<storage, read_write> size: array<LineSize>;
+var<storage, read> size: array<LineSize>;
 
 @group(0)
 @binding(2)
@@ -29,28 +45,36 @@ var<storage, read_write> glyph_data: array<GlyphData>;
 @binding(3)
 var<storage, read_write> layout_offset: array<f32>;
 
-const wgsize: u32 = 4;
+@group(0)
+@binding(4)
+var<storage, read_write> positions: array<GlyphPosition>;
+
+const ALIGN_RIGHT: u32 = 1;
+const ALIGN_CENTER: u32 = 2;
+const ALIGN_JUSTIFY: u32 = 3;
+
+const GAP: u32 = 1;
+
+const wgsize: u32 = 64;
 const n: u32 = wgsize * 2;
 
-var<workgroup> shared_data: array<f32, n>;
+// the advance of each glyph of a chunk of the line, and whether it's a gap between words
+var<workgroup> shared_data: array<vec2<f32>, n>;
 
-fn glyph(id: u32) -> f32 {
-    let data_id = text[id];
-    return glyph_data[u32(data_id)].advance_x;
+fn glyph(line: LineSize, index: u32) -> vec2<f32> {
+    if (index >= line.length) {
+        return vec2<f32>(0.0);
+    }
+    let position = positions[line.start + index];
+    return vec2<f32>(position.advance_x, f32((position.flags & GAP) != 0u));
 }
 
-@compute
-@workgroup_size(4, 1)
-fn main(
-    @builtin(local_invocation_id) local_id: vec3<u32>,
-    @builtin(workgroup_id) workgroup_id: vec3<u32>
-) {
-    let lineSize = size[workgroup_id.y];
-    let start = lineSize.start;
-    let tid = local_id.x;
+// Runs an exclusive prefix sum over the `n` glyphs of the line from `chunk` onwards, leaving the
+// result in `shared_data`. Returns the sum of the whole chunk.
+fn scan_chunk(line: LineSize, chunk: u32, tid: u32) -> vec2<f32> {
     var offset: u32 = 1u;
-    shared_data[2*tid] = glyph(start + 2*tid);
-    shared_data[2*tid + 1] = glyph(start + 2*tid + 1);
+    shared_data[2*tid] = glyph(line, chunk + 2*tid);
+    shared_data[2*tid + 1] = glyph(line, chunk + 2*tid + 1);
 
     for (var d: u32 = wgsize; d > 0; d = d >> 1) {
         workgroupBarrier();
@@ -62,8 +86,11 @@ fn main(
         offset = offset * 2;
     }
 
+    workgroupBarrier();
+    let total = shared_data[n - 1];
+    workgroupBarrier();
     if (tid == 0) {
-        shared_data[n - 1] = 0.0;
+        shared_data[n - 1] = vec2<f32>(0.0);
     }
 
     for (var d: u32 = 1; d < n; d = d * 2) {
@@ -79,7 +106,58 @@ fn main(
     }
 
     workgroupBarrier();
+    return total;
+}
+
+@compute
+@workgroup_size(wgsize, 1)
+fn main(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>
+) {
+    let line = size[workgroup_id.y];
+    let tid = local_id.x;
+
+    // the final values of the prefix sum are the width of the line and how many gaps it has
+    var total = vec2<f32>(0.0);
+    for (var chunk: u32 = 0; chunk < line.length; chunk += n) {
+        total += scan_chunk(line, chunk, tid);
+    }
 
-    layout_offset[start + 2*tid] = shared_data[2*tid];
-    layout_offset[start + 2*tid + 1] = shared_data[2*tid + 1];
-}
\ No newline at end of file
+    // glyphs are rasterized at the subpixel offset that shaping put them at, so the line is only
+    // ever moved by whole pixels
+    let space = line.width - total.x;
+    var shift = 0.0;
+    var extra = 0.0;
+    switch line.align {
+        case ALIGN_RIGHT: {
+            shift = round(space);
+        }
+        case ALIGN_CENTER: {
+            shift = floor(space / 2.0);
+        }
+        case ALIGN_JUSTIFY: {
+            if (total.y > 0.0) {
+                extra = max(space, 0.0) / total.y;
+            }
+        }
+        default: {}
+    }
+
+    // lay the line out again from the aligned edge, with the gaps stretched. Each gap is stretched
+    // by a whole number of pixels, and all of them together by the space left on the line, rounded.
+    var carry = vec2<f32>(shift, 0.0);
+    for (var chunk: u32 = 0; chunk < line.length; chunk += n) {
+        let sum = scan_chunk(line, chunk, tid);
+        for (var k: u32 = 0; k < 2; k++) {
+            let index = chunk + 2*tid + k;
+            if (index < line.length) {
+                let prefix = carry + shared_data[2*tid + k];
+                layout_offset[line.start + index] = prefix.x + round(prefix.y * extra);
+            }
+        }
+        carry += sum;
+        // every invocation has to be done reading the chunk before the next one replaces it
+        workgroupBarrier();
+    }
+}
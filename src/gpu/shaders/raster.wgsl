@@ -0,0 +1,99 @@
+const LINE: u32 = 0u;
+const QUAD: u32 = 1u;
+const CUBIC: u32 = 2u;
+
+// how many rows of samples are taken in each pixel. coverage along a row is exact, so more rows
+// only smooth out edges that are close to horizontal
+const SAMPLE_ROWS: u32 = 4u;
+// how many lines each curve is split into
+const CURVE_STEPS: u32 = 8u;
+
+struct Segment {
+    kind: u32,
+    points: array<vec2<f32>, 4>,
+}
+
+struct RasterJob {
+    first_segment: u32,
+    segments: u32,
+    width: u32,
+    height: u32,
+    left: i32,
+    top: i32,
+    offset: u32,
+    stride: u32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> segments: array<Segment>;
+
+@group(0)
+@binding(1)
+var<storage, read> jobs: array<RasterJob>;
+
+@group(0)
+@binding(2)
+var<storage, read_write> coverage: array<u32>;
+
+// adds how much of each of four pixels is right of where the line from `a` to `b` crosses the
+// row at `y`, signed by which way the line goes, so that the total over a whole outline is the
+// covered fraction of each pixel along that row
+fn add_crossing(a: vec2<f32>, b: vec2<f32>, y: f32, x: f32, covered: ptr<function, vec4<f32>>) {
+    if ((a.y <= y) == (b.y <= y)) {
+        return;
+    }
+    let crossing = a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y);
+    let direction = select(-1.0, 1.0, b.y > a.y);
+    let right_edges = vec4<f32>(x + 1.0, x + 2.0, x + 3.0, x + 4.0);
+    *covered += direction * clamp(right_edges - crossing, vec4<f32>(0.0), vec4<f32>(1.0));
+}
+
+fn curve_point(segment: Segment, t: f32) -> vec2<f32> {
+    let u = 1.0 - t;
+    let p = segment.points;
+    if (segment.kind == QUAD) {
+        return p[0] * (u * u) + p[1] * (2.0 * u * t) + p[2] * (t * t);
+    }
+    return p[0] * (u * u * u) + p[1] * (3.0 * u * u * t) + p[2] * (3.0 * u * t * t) + p[3] * (t * t * t);
+}
+
+@compute
+@workgroup_size(64, 1)
+fn main(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>
+) {
+    let job = jobs[workgroup_id.x];
+    // each invocation fills in words of four pixels, so that no two write to the same word
+    let words_per_row = (job.width + 3u) / 4u;
+    for (var word = local_id.x; word < words_per_row * job.height; word += 64u) {
+        let row = word / words_per_row;
+        let column = (word % words_per_row) * 4u;
+        let x = f32(job.left) + f32(column);
+
+        var total = vec4<f32>(0.0);
+        for (var sample = 0u; sample < SAMPLE_ROWS; sample++) {
+            // outlines have y pointing up, while rows count down from the top
+            let y = f32(job.top) - f32(row) - (f32(sample) + 0.5) / f32(SAMPLE_ROWS);
+            var covered = vec4<f32>(0.0);
+            for (var index = 0u; index < job.segments; index++) {
+                let segment = segments[job.first_segment + index];
+                if (segment.kind == LINE) {
+                    add_crossing(segment.points[0], segment.points[1], y, x, &covered);
+                    continue;
+                }
+                var a = segment.points[0];
+                for (var step = 1u; step <= CURVE_STEPS; step++) {
+                    let b = curve_point(segment, f32(step) / f32(CURVE_STEPS));
+                    add_crossing(a, b, y, x, &covered);
+                    a = b;
+                }
+            }
+            total += min(abs(covered), vec4<f32>(1.0));
+        }
+
+        let pixels = total / f32(SAMPLE_ROWS);
+        coverage[(job.offset + row * job.stride + column) / 4u] = pack4x8unorm(pixels);
+    }
+}
@@ -1,30 +1,105 @@
+const MASK: u32 = 0u;
+const COLOR: u32 = 1u;
+const SDF: u32 = 2u;
+const MSDF: u32 = 3u;
+
+struct RenderParams {
+    text_color: vec4<f32>,
+    edge_softness: f32,
+}
+
+struct Viewport {
+    width: f32,
+    height: f32,
+}
+
 struct VertexInput {
     @location(0) position: vec2<f32>,
     @location(1) texture_position: vec2<f32>,
+    @location(2) page: u32,
+    @location(3) content: u32,
+    @location(4) spread: f32,
 }
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) texture_position: vec2<f32>,
+    @location(1) @interpolate(flat) page: u32,
+    @location(2) @interpolate(flat) content: u32,
+    @location(3) @interpolate(flat) spread: f32,
 }
 
+@group(0) @binding(4)
+var<uniform> viewport: Viewport;
+
 @vertex
 fn vs_main(
     @builtin(vertex_index) vertex_index: u32,
     vertex: VertexInput
 ) -> VertexOutput {
     var out: VertexOutput;
-    out.position = vec4<f32>(vertex.position.x / 64, vertex.position.y / 64, 0, 1);
+    // positions are in pixels from the top left, while clip space goes from -1 to 1 upwards
+    let size = vec2<f32>(viewport.width, viewport.height);
+    let clip = vertex.position / size * 2.0 - 1.0;
+    out.position = vec4<f32>(clip.x, -clip.y, 0, 1);
     out.texture_position = vertex.texture_position;
+    out.page = vertex.page;
+    out.content = vertex.content;
+    out.spread = vertex.spread;
     return out;
 }
 
 @group(0) @binding(0)
-var atlas_texture: texture_2d<f32>;
+var mask_texture: texture_2d_array<f32>;
 @group(0) @binding(1)
+var color_texture: texture_2d_array<f32>;
+@group(0) @binding(2)
 var atlas_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> params: RenderParams;
+
+fn median(a: f32, b: f32, c: f32) -> f32 {
+    return max(min(a, b), min(max(a, b), c));
+}
+
+// turns a distance read from a field into coverage, where the field stores the outline at 0.5 and
+// the glyph's spread in atlas pixels in or out at 1 or 0
+fn field_coverage(distance: f32, screen_range: f32) -> f32 {
+    let screen_distance = (distance - 0.5) * screen_range;
+    return clamp(screen_distance / params.edge_softness + 0.5, 0.0, 1.0);
+}
+
+// every case writes premultiplied color, which is what color glyphs are stored in the atlas as
+fn premultiplied(color: vec3<f32>, alpha: f32) -> vec4<f32> {
+    return vec4<f32>(color * alpha, alpha);
+}
 
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(atlas_texture, atlas_sampler, vertex.texture_position);
-}
\ No newline at end of file
+    // both atlases are sampled so that the samples stay in uniform control flow
+    let mask = textureSample(mask_texture, atlas_sampler, vertex.texture_position, vertex.page);
+    let color = textureSample(color_texture, atlas_sampler, vertex.texture_position, vertex.page);
+    // how many screen pixels the whole range of the field covers, which is what makes the edge
+    // stay one pixel wide however much the glyph is scaled
+    let atlas_size = vec2<f32>(textureDimensions(mask_texture));
+    let unit_range = vec2<f32>(2.0 * vertex.spread) / atlas_size;
+    let screen_texture_size = vec2<f32>(1.0) / fwidth(vertex.texture_position);
+    let screen_range = max(0.5 * dot(unit_range, screen_texture_size), 1.0);
+    let text_color = params.text_color;
+    switch vertex.content {
+        case MASK: {
+            return premultiplied(text_color.rgb, text_color.a * mask.r);
+        }
+        case SDF: {
+            return premultiplied(text_color.rgb, text_color.a * field_coverage(mask.r, screen_range));
+        }
+        case MSDF: {
+            let distance = median(color.r, color.g, color.b);
+            return premultiplied(text_color.rgb, text_color.a * field_coverage(distance, screen_range));
+        }
+        default: {
+            // color glyphs keep their own colors, and are already premultiplied
+            return color;
+        }
+    }
+}
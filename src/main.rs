@@ -30,26 +30,26 @@ macro_rules! dbg_m {
     };
 }
 
-mod font;
-mod gpu;
-mod preproc;
-
 use std::{iter, mem::size_of, path::Path};
 
 use anyhow::Result;
-use gpu::{
-    command::{Command, CommandList},
-    generator::GenerationPass,
-    layout::LayoutPass,
-    publish::{
-        create_atlas_buffer, create_atlas_texture, publish_text, write_atlas_buffer,
-        write_atlas_texture,
+use image::{GrayImage, RgbaImage};
+use titan_text::{
+    font,
+    gpu::{
+        command::{Command, CommandList},
+        generator::GenerationPass,
+        layout::LayoutPass,
+        publish::{
+            create_atlas_buffer, create_atlas_texture, publish_text, write_atlas_buffer,
+            write_atlas_texture,
+        },
+        raster::RasterPass,
+        render::RenderPass,
+        GpuGlyphData, LineSize, Vertex,
     },
-    render::RenderPass,
+    preproc::{self, AtlasKind},
 };
-use image::RgbaImage;
-
-use crate::gpu::{GpuGlyphData, LineSize, Vertex};
 
 fn main() -> Result<()> {
     pollster::block_on(run())
@@ -58,28 +58,48 @@ fn main() -> Result<()> {
 const SIZE: u32 = 64;
 
 async fn run() -> Result<()> {
-    let mut buf = Vec::new();
-    let mut loader = font::Loader::system(&mut buf);
+    let mut loader = font::Loader::system();
     let query = fontdb::Query {
         families: &[fontdb::Family::SansSerif],
         weight: fontdb::Weight::NORMAL,
         stretch: fontdb::Stretch::Normal,
         style: fontdb::Style::Normal,
     };
-    let font = loader.load_font(&query)?;
+    let font = loader.load_font(&query)?.font;
     let atlas = preproc::Atlas::new(1024, 1024);
-    let mut proc = preproc::Preprocessor::new(font, atlas, 12.);
+    let fonts = font::FontStack::new(font).with_fallback(loader);
+    let mut proc = preproc::Preprocessor::new(fonts, atlas, 12.);
     proc.add_str("hi")?;
 
     let (device, queue) = load_gpu().await?;
-    let atlas_texture = create_atlas_texture(proc.atlas.as_atlas_view(), &device);
-    write_atlas_texture(proc.atlas.as_atlas_view(), &atlas_texture, &queue);
+    let mask_texture = create_atlas_texture(proc.atlas.as_atlas_view(), AtlasKind::Mask, &device);
+    write_atlas_texture(
+        proc.atlas.as_atlas_view(),
+        AtlasKind::Mask,
+        &mask_texture,
+        &queue,
+    );
+    let color_texture = create_atlas_texture(proc.atlas.as_atlas_view(), AtlasKind::Color, &device);
+    write_atlas_texture(
+        proc.atlas.as_atlas_view(),
+        AtlasKind::Color,
+        &color_texture,
+        &queue,
+    );
     let glyph_data_buffer = create_atlas_buffer(proc.atlas.as_atlas_view(), &device);
     write_atlas_buffer(proc.atlas.as_atlas_view(), &glyph_data_buffer, &queue);
     let text = publish_text(&proc.text, &device, &queue);
 
-    queue.submit([]);
-    save_atlas_texture(&atlas_texture, &device, &queue, "atlas.bmp").await;
+    let raster_pass = RasterPass::new(
+        &device,
+        proc.atlas.as_atlas_view().entries.values(),
+        &mask_texture,
+    );
+    let mut commands = CommandList::default();
+    raster_pass.push_buffers(&device, &mut commands);
+    commands.submit(&queue);
+    save_atlas_texture(&mask_texture, &device, &queue, "atlas.bmp").await;
+    save_atlas_texture(&color_texture, &device, &queue, "color_atlas.bmp").await;
 
     let layout_pass = LayoutPass::new(&device, &text, &glyph_data_buffer);
     let generate_pass = GenerationPass::new(
@@ -87,6 +107,7 @@ async fn run() -> Result<()> {
         &text,
         &glyph_data_buffer,
         &layout_pass.layout_buffer,
+        proc.atlas.as_atlas_view(),
     );
 
     let mut commands = CommandList::default();
@@ -103,7 +124,8 @@ async fn run() -> Result<()> {
         &render_output,
         &generate_pass.vertex_buffer,
         &generate_pass.index_buffer,
-        &atlas_texture,
+        &mask_texture,
+        &color_texture,
         text.glyphs * 6,
     );
 
@@ -170,7 +192,7 @@ async fn load_buffer_of<T: bytemuck::Pod>(
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Get Num Indices Encoder"),
         });
-        encoder.copy_buffer_to_buffer(&buffer, 0, &debug_buffer, 0, buffer.size());
+        encoder.copy_buffer_to_buffer(buffer, 0, debug_buffer, 0, buffer.size());
         queue.submit(iter::once(encoder.finish()));
         let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
         let buffer_slice = debug_buffer.slice(..);
@@ -180,7 +202,7 @@ async fn load_buffer_of<T: bytemuck::Pod>(
         device.poll(wgpu::Maintain::Wait);
         rx.receive().await.unwrap().unwrap();
         let data = buffer_slice.get_mapped_range();
-        data.into_iter()
+        data.iter()
             .copied()
             .take(elements * size_of::<T>())
             .collect()
@@ -204,7 +226,10 @@ async fn save_atlas_texture(
     queue: &wgpu::Queue,
     file: impl AsRef<Path>,
 ) {
-    let buffer_size = (texture.size().width * texture.size().height * 4 * (size_of::<u8>() as u32))
+    // every layer of the atlas is saved one below the other
+    let layers = texture.depth_or_array_layers();
+    let bytes_per_pixel = texture.format().block_copy_size(None).unwrap();
+    let buffer_size = (texture.size().width * texture.size().height * layers * bytes_per_pixel)
         as wgpu::BufferAddress;
     let buffer = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Output Buffer"),
@@ -217,7 +242,7 @@ async fn save_atlas_texture(
     });
     encoder.copy_texture_to_buffer(
         wgpu::ImageCopyTextureBase {
-            texture: &texture,
+            texture,
             mip_level: 0,
             origin: wgpu::Origin3d::ZERO,
             aspect: wgpu::TextureAspect::All,
@@ -226,7 +251,7 @@ async fn save_atlas_texture(
             buffer: &buffer,
             layout: wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(size_of::<u32>() as u32 * texture.width()),
+                bytes_per_row: Some(bytes_per_pixel * texture.width()),
                 rows_per_image: Some(texture.height()),
             },
         },
@@ -243,9 +268,16 @@ async fn save_atlas_texture(
 
         let data = buffer_slice.get_mapped_range();
 
-        let image =
-            RgbaImage::from_raw(texture.width(), texture.height(), (&*data).to_owned()).unwrap();
-        image.save(file).unwrap();
+        let (width, height) = (texture.width(), texture.height() * layers);
+        match bytes_per_pixel {
+            1 => GrayImage::from_raw(width, height, data.to_vec())
+                .unwrap()
+                .save(file),
+            _ => RgbaImage::from_raw(width, height, data.to_vec())
+                .unwrap()
+                .save(file),
+        }
+        .unwrap();
     }
 }
 
@@ -267,7 +299,7 @@ async fn save_output_texture(
     });
     encoder.copy_texture_to_buffer(
         wgpu::ImageCopyTextureBase {
-            texture: &texture,
+            texture,
             mip_level: 0,
             origin: wgpu::Origin3d::ZERO,
             aspect: wgpu::TextureAspect::All,
@@ -293,7 +325,7 @@ async fn save_output_texture(
 
         let data = buffer_slice.get_mapped_range();
 
-        let image = RgbaImage::from_raw(SIZE, SIZE, (&*data).to_owned()).unwrap();
+        let image = RgbaImage::from_raw(SIZE, SIZE, data.to_vec()).unwrap();
         image.save(file).unwrap();
     }
 }
@@ -0,0 +1,29 @@
+/// Where a line is put between the edges of the width it's laid out in
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Align {
+    /// against the edge the line's base direction starts at, which is the left for left to right
+    /// text and the right for right to left text
+    #[default]
+    Start,
+    /// against the edge the line's base direction ends at
+    End,
+    Left,
+    Right,
+    Center,
+    /// against both edges, with the extra width spread evenly over the spaces between words. The
+    /// last line of a paragraph isn't stretched and is put at its start instead.
+    Justify,
+}
+
+impl Align {
+    /// Turns start and end into left or right for a line whose base direction is right to left
+    /// if `rtl` is set. `last` is whether the line ends its paragraph, which is never justified.
+    pub fn resolve(self, rtl: bool, last: bool) -> Self {
+        match (self, rtl) {
+            (Align::Justify, _) if last => Align::Start.resolve(rtl, last),
+            (Align::Start, false) | (Align::End, true) => Align::Left,
+            (Align::Start, true) | (Align::End, false) => Align::Right,
+            (align, _) => align,
+        }
+    }
+}
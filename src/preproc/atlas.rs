@@ -1,76 +1,635 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::font::LoadedGlyph;
+use swash::{scale::image::Content, GlyphId};
+use thiserror::Error;
+
+use crate::font::{FontId, GlyphMode, LoadedGlyph, RasterOptions, Synthesis};
+
+use super::{
+    packer::{Occupancy, ShelfPacker},
+    Text,
+};
 
 pub type AtlasID = u16;
 
+/// Bits describing how a glyph was styled when it was rasterized
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StyleFlags(pub u8);
+
+/// Everything that determines what a rasterized glyph looks like, so that glyphs from different
+/// fonts and sizes can share one atlas
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    /// the font in the preprocessor's font stack that the glyph was rendered with
+    pub font_id: FontId,
+    /// the glyph after shaping, so OpenType features that substitute glyphs, like small caps,
+    /// already end up with their own entries
+    pub glyph_id: GlyphId,
+    /// the bits of the `f32` point size, since floats can't be hashed
+    pub size: u32,
+    /// which horizontal subpixel offset the glyph was rasterized at, out of
+    /// `raster.subpixel_bins`
+    pub subpixel_bin: u8,
+    pub raster: RasterOptions,
+    pub style: StyleFlags,
+    pub mode: GlyphMode,
+    /// which instance of a variable font the glyph came from, see `Font::instance_key`
+    pub instance: u64,
+    /// set for a box drawn with the hex code of a character that no font has, see
+    /// `MissingGlyph::HexBox`, in which case `glyph_id` isn't used
+    pub hex_box: Option<char>,
+}
+
+/// Which of the atlas textures a glyph is stored in
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AtlasKind {
+    /// a single channel of coverage, which is tinted with the text color when drawn
+    Mask,
+    /// four channels, for color glyphs and multi-channel distance fields
+    Color,
+}
+
 pub struct Atlas {
-    entries: HashMap<u32, AtlasGlyph>,
+    entries: HashMap<GlyphKey, AtlasGlyph>,
     width: u32,
     height: u32,
-    next_entry_x: u32,
-    next_entry_y: u32,
-    next_id: u16,
-    glyph_height: u32,
+    padding: u32,
+    /// one packer for each layer of the mask atlas texture
+    mask_pages: Vec<ShelfPacker>,
+    /// one packer for each layer of the color atlas texture
+    color_pages: Vec<ShelfPacker>,
+    max_pages: u32,
+    /// the key of the glyph that owns each id, or `None` if the glyph was evicted
+    slots: Vec<Option<GlyphKey>>,
+    /// evicted ids, which are handed out again before any new ones
+    free_ids: Vec<AtlasID>,
+    frame: u64,
+    /// everything that changed since the last call to `take_updates`
+    updates: AtlasUpdates,
+}
+
+/// A rectangle on one page of one of the atlas textures
+#[derive(Copy, Clone, Debug)]
+pub struct DirtyRect {
+    pub kind: AtlasKind,
+    pub page: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The changes that have to be uploaded to keep a copy of the atlas on the GPU in sync
+#[derive(Debug, Default)]
+pub struct AtlasUpdates {
+    /// ids whose glyph was stored or evicted. Evicted ids that haven't been reused have no glyph.
+    pub glyphs: Vec<AtlasID>,
+    /// the spaces of evicted glyphs, which should be cleared before new glyphs are written
+    pub cleared: Vec<DirtyRect>,
+}
+
+#[derive(Copy, Clone, Debug, Error)]
+#[error("The atlas has no room left for a {width}x{height} glyph")]
+pub struct AtlasFull {
+    pub width: u32,
+    pub height: u32,
 }
 
 #[derive(Debug)]
 pub struct AtlasGlyph {
     pub id: AtlasID,
+    pub key: GlyphKey,
     pub glyph: LoadedGlyph,
+    /// the layer of the atlas texture that the glyph is stored in
+    pub page: u32,
     pub x: u32,
     pub y: u32,
+    /// the last frame that the glyph was used in
+    pub last_used: u64,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct AtlasView<'a> {
-    pub entries: &'a HashMap<u32, AtlasGlyph>,
+    pub entries: &'a HashMap<GlyphKey, AtlasGlyph>,
     pub width: u32,
     pub height: u32,
+    pub mask_pages: u32,
+    pub color_pages: u32,
+    /// how many ids have been handed out, including those of evicted glyphs
+    pub ids: u32,
+}
+
+impl StyleFlags {
+    pub const NONE: Self = Self(0);
+    pub const BOLD: Self = Self(1);
+    pub const OBLIQUE: Self = Self(2);
+
+    pub fn contains(self, flags: Self) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+}
+
+impl From<Synthesis> for StyleFlags {
+    fn from(synthesis: Synthesis) -> Self {
+        let mut flags = Self::NONE.0;
+        if synthesis.bold {
+            flags |= Self::BOLD.0;
+        }
+        if synthesis.oblique {
+            flags |= Self::OBLIQUE.0;
+        }
+        Self(flags)
+    }
+}
+
+impl AtlasKind {
+    pub fn of(content: Content) -> Self {
+        match content {
+            Content::Mask => Self::Mask,
+            Content::SubpixelMask | Content::Color => Self::Color,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> u32 {
+        match self {
+            Self::Mask => 1,
+            Self::Color => 4,
+        }
+    }
+}
+
+impl AtlasGlyph {
+    pub fn kind(&self) -> AtlasKind {
+        AtlasKind::of(self.glyph.image.content)
+    }
+
+    /// The space the glyph's image takes up in the atlas
+    pub fn rect(&self) -> DirtyRect {
+        DirtyRect {
+            kind: self.kind(),
+            page: self.page,
+            x: self.x,
+            y: self.y,
+            width: self.glyph.image.placement.width,
+            height: self.glyph.image.placement.height,
+        }
+    }
+}
+
+impl GlyphKey {
+    pub fn new(font_id: FontId, glyph_id: GlyphId, size: f32) -> Self {
+        Self {
+            font_id,
+            glyph_id,
+            size: size.to_bits(),
+            subpixel_bin: 0,
+            raster: RasterOptions::default(),
+            style: StyleFlags::NONE,
+            mode: GlyphMode::Coverage,
+            instance: 0,
+            hex_box: None,
+        }
+    }
+
+    pub fn size(&self) -> f32 {
+        f32::from_bits(self.size)
+    }
+
+    /// The key that the glyph is stored in the atlas under, and how many times bigger than its
+    /// image it's drawn. Distance fields are only rasterized at `GlyphMode::FIELD_SIZE`, so their
+    /// size is left out of the key and made up for by scaling.
+    pub fn stored(self) -> (Self, f32) {
+        if !self.mode.is_field() {
+            return (self, 1.);
+        }
+        let key = Self {
+            size: GlyphMode::FIELD_SIZE.to_bits(),
+            ..self
+        };
+        (key, self.size() / GlyphMode::FIELD_SIZE)
+    }
 }
 
 impl Atlas {
+    /// The number of empty pixels left between neighbouring glyphs, so that linear sampling doesn't
+    /// bleed one glyph into the next
+    pub const DEFAULT_PADDING: u32 = 1;
+    /// The most layers the atlas texture can have, which is the smallest limit that wgpu allows
+    /// devices to have
+    pub const MAX_PAGES: u32 = 256;
+
     pub fn new(width: u32, height: u32) -> Self {
+        Self::with_padding(width, height, Self::DEFAULT_PADDING)
+    }
+
+    pub fn with_padding(width: u32, height: u32, padding: u32) -> Self {
         Self {
             entries: HashMap::new(),
             width,
             height,
-            next_entry_x: 0,
-            next_entry_y: 0,
-            next_id: 0,
-            glyph_height: 0,
-        }
-    }
-
-    pub fn store_glyph(&mut self, codepoint: impl Into<u32>, glyph: LoadedGlyph) {
-        self.glyph_height = self.glyph_height.max(glyph.image.placement.height);
-        let outer_edge = glyph.image.placement.width + self.next_entry_x;
-        let (x, y) = if outer_edge >= self.width {
-            self.next_entry_y += self.glyph_height;
-            self.next_entry_x = glyph.image.placement.width;
-            (0, self.next_entry_y)
-        } else {
-            let p = (self.next_entry_x, self.next_entry_y);
-            self.next_entry_x += glyph.image.placement.width;
-            p
+            padding,
+            mask_pages: vec![ShelfPacker::new(width, height, padding)],
+            color_pages: Vec::new(),
+            max_pages: Self::MAX_PAGES,
+            slots: Vec::new(),
+            free_ids: Vec::new(),
+            frame: 0,
+            updates: AtlasUpdates::default(),
+        }
+    }
+
+    /// Limits each atlas texture to `pages` layers, after which glyphs have to be evicted to make room
+    pub fn limit_pages(mut self, pages: u32) -> Self {
+        self.max_pages = pages.clamp(1, Self::MAX_PAGES);
+        self
+    }
+
+    fn pages(&self, kind: AtlasKind) -> &Vec<ShelfPacker> {
+        match kind {
+            AtlasKind::Mask => &self.mask_pages,
+            AtlasKind::Color => &self.color_pages,
+        }
+    }
+
+    fn pages_mut(&mut self, kind: AtlasKind) -> &mut Vec<ShelfPacker> {
+        match kind {
+            AtlasKind::Mask => &mut self.mask_pages,
+            AtlasKind::Color => &mut self.color_pages,
+        }
+    }
+
+    /// Finds room for a glyph on the first page of its texture that has some, adding a new page if
+    /// they're all full
+    fn allocate(
+        &mut self,
+        kind: AtlasKind,
+        width: u32,
+        height: u32,
+    ) -> Result<(u32, u32, u32), AtlasFull> {
+        // glyphs without any pixels, like space, don't take up a rectangle on any page
+        if width == 0 || height == 0 {
+            return Ok((0, 0, 0));
+        }
+        let (atlas_width, atlas_height, padding, max_pages) =
+            (self.width, self.height, self.padding, self.max_pages);
+        let pages = self.pages_mut(kind);
+        let found = pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(page, packer)| Some((page as u32, packer.allocate(width, height)?)));
+        if let Some((page, (x, y))) = found {
+            return Ok((page, x, y));
+        }
+        if pages.len() as u32 >= max_pages {
+            return Err(AtlasFull { width, height });
+        }
+        let mut packer = ShelfPacker::new(atlas_width, atlas_height, padding);
+        let (x, y) = packer
+            .allocate(width, height)
+            .ok_or(AtlasFull { width, height })?;
+        pages.push(packer);
+        Ok((pages.len() as u32 - 1, x, y))
+    }
+
+    pub fn store_glyph(&mut self, key: GlyphKey, glyph: LoadedGlyph) -> Result<AtlasID, AtlasFull> {
+        let placement = glyph.image.placement;
+        if self.free_ids.is_empty() && self.slots.len() > AtlasID::MAX as usize {
+            return Err(AtlasFull {
+                width: placement.width,
+                height: placement.height,
+            });
+        }
+        let kind = AtlasKind::of(glyph.image.content);
+        let (page, x, y) = self.allocate(kind, placement.width, placement.height)?;
+        let id = match self.free_ids.pop() {
+            Some(id) => {
+                self.slots[id as usize] = Some(key);
+                id
+            }
+            None => {
+                self.slots.push(Some(key));
+                (self.slots.len() - 1) as AtlasID
+            }
         };
-        let id = self.next_id;
-        self.next_id += 1;
 
+        self.entries.insert(
+            key,
+            AtlasGlyph {
+                id,
+                key,
+                glyph,
+                page,
+                x,
+                y,
+                last_used: self.frame,
+            },
+        );
+        self.updates.glyphs.push(id);
+        Ok(id)
+    }
+
+    /// Stores a glyph like `store_glyph`, but if the atlas is full or out of ids, evicts the least
+    /// recently used glyphs until there is room. Glyphs used this frame or referenced by `live` are never evicted.
+    pub fn store_glyph_evicting(
+        &mut self,
+        key: GlyphKey,
+        glyph: LoadedGlyph,
+        live: &[&Text],
+    ) -> Result<AtlasID, AtlasFull> {
+        let placement = glyph.image.placement;
+        let full = AtlasFull {
+            width: placement.width,
+            height: placement.height,
+        };
+        let mut candidates = self.eviction_candidates(live);
+        // least recently used last, so they can be popped off
+        candidates.sort_by_key(|&(_, last_used)| std::cmp::Reverse(last_used));
+        loop {
+            let pages = self.pages(AtlasKind::of(glyph.image.content));
+            let has_room = pages
+                .iter()
+                .any(|packer| packer.can_allocate(placement.width, placement.height))
+                || (pages.len() as u32) < self.max_pages;
+            // every id can be taken even when there's room left, by lots of small glyphs
+            let has_id = !self.free_ids.is_empty() || self.slots.len() <= AtlasID::MAX as usize;
+            if has_room && has_id {
+                return self.store_glyph(key, glyph);
+            }
+            let (evicted, _) = candidates.pop().ok_or(full)?;
+            self.evict(evicted);
+        }
+    }
+
+    /// Starts a new frame. Glyphs used in earlier frames can be evicted, while glyphs used in this
+    /// one can't.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Looks up a glyph like `get_glyph_id`, marking it as used this frame
+    pub fn use_glyph(&mut self, key: GlyphKey) -> Option<AtlasID> {
+        let glyph = self.entries.get_mut(&key)?;
+        glyph.last_used = self.frame;
+        Some(glyph.id)
+    }
+
+    /// Marks every glyph in `text` as used this frame, for text that is still being drawn
+    pub fn mark_used(&mut self, text: &Text) {
+        for line in &text.lines {
+            for glyph in &line.glyphs {
+                if let Some(Some(key)) = self.slots.get(glyph.id as usize) {
+                    if let Some(entry) = self.entries.get_mut(key) {
+                        entry.last_used = self.frame;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Evicts every glyph that hasn't been used in the last `frames` frames and isn't referenced by
+    /// `live`, returning how many were evicted
+    pub fn evict_unused(&mut self, frames: u64, live: &[&Text]) -> usize {
+        let cutoff = self.frame.saturating_sub(frames);
+        let evicted: Vec<_> = self
+            .eviction_candidates(live)
+            .into_iter()
+            .filter(|&(_, last_used)| last_used < cutoff)
+            .collect();
+        for &(key, _) in &evicted {
+            self.evict(key);
+        }
+        evicted.len()
+    }
+
+    /// The keys and last uses of every glyph that could be evicted without breaking `live` text
+    fn eviction_candidates(&self, live: &[&Text]) -> Vec<(GlyphKey, u64)> {
+        let live: HashSet<AtlasID> = live
+            .iter()
+            .flat_map(|text| &text.lines)
+            .flat_map(|line| &line.glyphs)
+            .map(|glyph| glyph.id)
+            .collect();
         self.entries
-            .insert(codepoint.into(), AtlasGlyph { id, glyph, x, y });
+            .values()
+            .filter(|glyph| glyph.last_used < self.frame && !live.contains(&glyph.id))
+            .map(|glyph| (glyph.key, glyph.last_used))
+            .collect()
     }
 
-    pub fn get_glyph_id(&self, codepoint: impl Into<u32>) -> Option<AtlasID> {
-        let glyph = self.entries.get(&codepoint.into())?;
+    fn evict(&mut self, key: GlyphKey) {
+        let Some(glyph) = self.entries.remove(&key) else {
+            return;
+        };
+        let placement = glyph.glyph.image.placement;
+        if let Some(packer) = self.pages_mut(glyph.kind()).get_mut(glyph.page as usize) {
+            packer.deallocate(glyph.x, glyph.y, placement.width, placement.height);
+        }
+        self.slots[glyph.id as usize] = None;
+        self.free_ids.push(glyph.id);
+        self.updates.glyphs.push(glyph.id);
+        self.updates.cleared.push(glyph.rect());
+    }
+
+    pub fn occupancy(&self) -> Occupancy {
+        self.mask_pages
+            .iter()
+            .chain(&self.color_pages)
+            .map(ShelfPacker::occupancy)
+            .fold(Occupancy::default(), |total, page| Occupancy {
+                rects: total.rects + page.rects,
+                used_area: total.used_area + page.used_area,
+                shelf_area: total.shelf_area + page.shelf_area,
+                total_area: total.total_area + page.total_area,
+            })
+    }
+
+    pub fn page_occupancy(&self, kind: AtlasKind, page: u32) -> Option<Occupancy> {
+        Some(self.pages(kind).get(page as usize)?.occupancy())
+    }
+
+    /// Takes every change made since the last call, with each id listed once
+    pub fn take_updates(&mut self) -> AtlasUpdates {
+        let mut updates = std::mem::take(&mut self.updates);
+        updates.glyphs.sort_unstable();
+        updates.glyphs.dedup();
+        updates
+    }
+
+    pub fn glyph(&self, id: AtlasID) -> Option<&AtlasGlyph> {
+        let key = self.slots.get(id as usize)?.as_ref()?;
+        self.entries.get(key)
+    }
+
+    pub fn get_glyph_id(&self, key: GlyphKey) -> Option<AtlasID> {
+        let glyph = self.entries.get(&key)?;
         Some(glyph.id)
     }
 
-    pub fn as_atlas_view(&self) -> AtlasView {
+    pub fn as_atlas_view(&self) -> AtlasView<'_> {
         AtlasView {
             entries: &self.entries,
             width: self.width,
             height: self.height,
+            mask_pages: self.mask_pages.len() as u32,
+            color_pages: self.color_pages.len() as u32,
+            ids: self.slots.len() as u32,
         }
     }
 }
+
+impl AtlasView<'_> {
+    pub fn pages(&self, kind: AtlasKind) -> u32 {
+        match kind {
+            AtlasKind::Mask => self.mask_pages,
+            AtlasKind::Color => self.color_pages,
+        }
+    }
+
+    /// The size of one layer of the atlas texture of `kind`
+    pub fn bytes_per_page(&self, kind: AtlasKind) -> u32 {
+        kind.bytes_per_pixel() * self.width * self.height
+    }
+
+    /// The pixels of the first `pages` layers of the atlas texture of `kind`, row by row from the
+    /// top of each layer. Glyphs that are rasterized on the GPU are left empty, since the raster
+    /// pass writes them instead.
+    pub fn pixels(&self, kind: AtlasKind, pages: u32) -> Vec<u8> {
+        let bytes_per_pixel = kind.bytes_per_pixel();
+        let bytes_per_row = bytes_per_pixel * self.width;
+        let bytes_per_page = self.bytes_per_page(kind);
+        let mut data = vec![0u8; (bytes_per_page * pages) as usize];
+        let glyphs = self.entries.values().filter(|glyph| {
+            glyph.kind() == kind && glyph.glyph.mode != GlyphMode::Gpu && glyph.page < pages
+        });
+        for glyph in glyphs {
+            let placement = glyph.glyph.image.placement;
+            let glyph_row = (bytes_per_pixel * placement.width) as usize;
+            for (local_y, row) in glyph
+                .glyph
+                .image
+                .data
+                .chunks_exact(glyph_row.max(1))
+                .enumerate()
+            {
+                let index = bytes_per_pixel * glyph.x
+                    + bytes_per_row * (glyph.y + local_y as u32)
+                    + bytes_per_page * glyph.page;
+                data[index as usize..index as usize + glyph_row].copy_from_slice(row);
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use swash::{scale::image::Image, zeno::Placement};
+
+    use super::*;
+
+    fn mask(width: u32, height: u32) -> LoadedGlyph {
+        let mut image = Image::new();
+        image.content = Content::Mask;
+        image.placement = Placement {
+            left: 0,
+            top: 0,
+            width,
+            height,
+        };
+        image.data = vec![255; (width * height) as usize];
+        LoadedGlyph {
+            image,
+            advance_width: width as f32,
+            mode: GlyphMode::Coverage,
+            outline: Vec::new(),
+        }
+    }
+
+    fn color(width: u32, height: u32) -> LoadedGlyph {
+        let mut glyph = mask(width, height);
+        glyph.image.content = Content::Color;
+        glyph.image.data = vec![255; (width * height * 4) as usize];
+        glyph
+    }
+
+    #[test]
+    fn mask_and_color_glyphs_go_to_their_own_textures() {
+        let mut atlas = Atlas::new(16, 8);
+        let mask_id = atlas
+            .store_glyph(GlyphKey::new(0, 1, 8.), mask(2, 3))
+            .unwrap();
+        let color_id = atlas
+            .store_glyph(GlyphKey::new(0, 2, 8.), color(4, 2))
+            .unwrap();
+        let mask_glyph = atlas.glyph(mask_id).unwrap();
+        let color_glyph = atlas.glyph(color_id).unwrap();
+        assert_eq!(mask_glyph.kind(), AtlasKind::Mask);
+        assert_eq!(color_glyph.kind(), AtlasKind::Color);
+        // each texture has its own first page, so both glyphs start in its corner
+        assert_eq!((mask_glyph.page, mask_glyph.x, mask_glyph.y), (0, 0, 0));
+        assert_eq!((color_glyph.page, color_glyph.x, color_glyph.y), (0, 0, 0));
+
+        let view = atlas.as_atlas_view();
+        assert_eq!((view.mask_pages, view.color_pages), (1, 1));
+        assert_eq!(view.bytes_per_page(AtlasKind::Mask), 16 * 8);
+        assert_eq!(view.bytes_per_page(AtlasKind::Color), 16 * 8 * 4);
+        let mask_pixels = view.pixels(AtlasKind::Mask, 2);
+        let color_pixels = view.pixels(AtlasKind::Color, 2);
+        assert_eq!(mask_pixels.len(), 16 * 8 * 2);
+        assert_eq!(color_pixels.len(), 16 * 8 * 4 * 2);
+        // every pixel of each page belongs to its own glyph and nothing else
+        let inked = |pixels: &[u8]| pixels.iter().filter(|&&byte| byte > 0).count();
+        assert_eq!(inked(&mask_pixels), 2 * 3);
+        assert_eq!(inked(&color_pixels), 4 * 2 * 4);
+        assert_eq!(&mask_pixels[16..18], &[255, 255]);
+        assert_eq!(&color_pixels[16 * 4..16 * 4 + 16], &[255; 16]);
+    }
+
+    #[test]
+    fn evicting_small_glyphs_makes_room_for_a_bigger_one() {
+        let mut atlas = Atlas::new(32, 8).limit_pages(1);
+        for glyph_id in 0..4 {
+            // the last glyph is used more recently than the three that the big one needs the room of
+            if glyph_id == 3 {
+                atlas.next_frame();
+            }
+            atlas
+                .store_glyph(GlyphKey::new(0, glyph_id, 8.), mask(7, 7))
+                .unwrap();
+        }
+        atlas.next_frame();
+        let id = atlas
+            .store_glyph_evicting(GlyphKey::new(0, 4, 8.), mask(20, 7), &[])
+            .unwrap();
+        let glyph = &atlas.entries[&GlyphKey::new(0, 4, 8.)];
+        assert_eq!((glyph.id, glyph.x, glyph.y), (id, 0, 0));
+        // the most recently used glyph didn't have to be evicted
+        assert_eq!(atlas.entries.len(), 2);
+        assert_eq!(atlas.occupancy().used_area, 20 * 7 + 7 * 7);
+    }
+
+    #[test]
+    fn evicting_frees_an_id_once_they_are_all_taken() {
+        // one page fits a glyph for every id
+        let mut atlas = Atlas::with_padding(256, 256, 0).limit_pages(1);
+        for glyph_id in 0..=AtlasID::MAX {
+            atlas
+                .store_glyph(GlyphKey::new(0, glyph_id, 8.), mask(1, 1))
+                .unwrap();
+        }
+        let key = GlyphKey::new(1, 0, 8.);
+        assert!(atlas.store_glyph(key, mask(1, 1)).is_err());
+        // nothing can be evicted while every glyph was used this frame
+        assert!(atlas.store_glyph_evicting(key, mask(1, 1), &[]).is_err());
+
+        atlas.next_frame();
+        let id = atlas.store_glyph_evicting(key, mask(1, 1), &[]).unwrap();
+        assert_eq!(atlas.slots.len(), AtlasID::MAX as usize + 1);
+        assert_eq!(atlas.glyph(id).map(|glyph| glyph.key), Some(key));
+        assert_eq!(atlas.entries.len(), AtlasID::MAX as usize + 1);
+    }
+}
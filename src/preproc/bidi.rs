@@ -0,0 +1,105 @@
+use unicode_bidi::{BidiInfo, Level};
+
+/// Puts glyphs that were shaped in logical order into the order they're drawn in, left to right,
+/// following rule L2 of UAX #9. `clusters` has the byte offset in the line of each glyph's
+/// cluster, and `levels` the embedding level of each byte of the line after rule L1. Returns the
+/// logical index of each glyph in visual order.
+///
+/// Glyphs of one shaper cluster are moved as a whole and keep their order, so that marks still
+/// come after the glyph they attach to.
+pub fn visual_order(clusters: &[u32], levels: &[Level]) -> Vec<usize> {
+    // the first glyph of each cluster
+    let starts: Vec<usize> = (0..clusters.len())
+        .filter(|&index| index == 0 || clusters[index] != clusters[index - 1])
+        .collect();
+    let cluster_levels: Vec<Level> = starts
+        .iter()
+        .map(|&start| levels[clusters[start] as usize])
+        .collect();
+    BidiInfo::reorder_visual(&cluster_levels)
+        .into_iter()
+        .flat_map(|cluster| {
+            let start = starts[cluster];
+            let end = starts.get(cluster + 1).copied().unwrap_or(clusters.len());
+            start..end
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The levels of every byte of a line, from the level of each of its characters
+    fn levels(chars: &[(char, u8)]) -> Vec<Level> {
+        chars
+            .iter()
+            .flat_map(|&(ch, level)| vec![Level::new(level).unwrap(); ch.len_utf8()])
+            .collect()
+    }
+
+    /// One glyph for every character, clustered by itself
+    fn clusters(chars: &[(char, u8)]) -> Vec<u32> {
+        chars
+            .iter()
+            .scan(0, |offset, &(ch, _)| {
+                let cluster = *offset;
+                *offset += ch.len_utf8() as u32;
+                Some(cluster)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn left_to_right_stays_in_order() {
+        let chars = [('a', 0), ('b', 0), ('c', 0)];
+        assert_eq!(visual_order(&clusters(&chars), &levels(&chars)), [0, 1, 2]);
+    }
+
+    #[test]
+    fn right_to_left_run_is_reversed_inside_left_to_right() {
+        let chars = [
+            ('a', 0),
+            (' ', 0),
+            ('\u{5D0}', 1),
+            ('\u{5D1}', 1),
+            ('\u{5D2}', 1),
+            (' ', 0),
+            ('b', 0),
+        ];
+        assert_eq!(
+            visual_order(&clusters(&chars), &levels(&chars)),
+            [0, 1, 4, 3, 2, 5, 6]
+        );
+    }
+
+    #[test]
+    fn left_to_right_run_keeps_its_order_inside_right_to_left() {
+        let chars = [
+            ('\u{5D0}', 1),
+            ('\u{5D1}', 1),
+            (' ', 1),
+            ('1', 2),
+            ('2', 2),
+            (' ', 1),
+            ('\u{5D2}', 1),
+        ];
+        assert_eq!(
+            visual_order(&clusters(&chars), &levels(&chars)),
+            [6, 5, 3, 4, 2, 1, 0]
+        );
+    }
+
+    #[test]
+    fn glyphs_of_a_cluster_keep_their_order() {
+        // two right to left clusters of a base and a mark each, then one of three glyphs
+        let levels = vec![Level::rtl(); 8];
+        let clusters = [0, 0, 2, 2, 4, 4, 4];
+        assert_eq!(visual_order(&clusters, &levels), [4, 5, 6, 2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn no_glyphs() {
+        assert!(visual_order(&[], &[]).is_empty());
+    }
+}
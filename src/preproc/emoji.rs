@@ -0,0 +1,199 @@
+use swash::text::Codepoint;
+
+const ZWJ: char = '\u{200D}';
+/// asks for the character before it to be drawn as text
+const TEXT_PRESENTATION: char = '\u{FE0E}';
+/// asks for the character before it to be drawn as a color emoji
+const EMOJI_PRESENTATION: char = '\u{FE0F}';
+const KEYCAP: char = '\u{20E3}';
+const CANCEL_TAG: char = '\u{E007F}';
+
+/// An emoji at the start of some text, which has to be shaped as a whole with one font so that
+/// the font can combine its parts into a single glyph
+#[derive(Copy, Clone, Debug)]
+pub struct Sequence {
+    /// how many bytes of the text the emoji takes up
+    pub len: usize,
+    /// whether the emoji should be drawn with a color font instead of like the text around it
+    pub color: bool,
+}
+
+/// Skin tones, which change the emoji before them
+fn is_modifier(ch: char) -> bool {
+    matches!(ch, '\u{1F3FB}'..='\u{1F3FF}')
+}
+
+/// Pairs of these letters make up country flags
+fn is_regional_indicator(ch: char) -> bool {
+    matches!(ch, '\u{1F1E6}'..='\u{1F1FF}')
+}
+
+/// Tag letters, which pick a subdivision flag like England's after a black flag
+fn is_tag(ch: char) -> bool {
+    matches!(ch, '\u{E0020}'..='\u{E007E}')
+}
+
+/// Whether `ch` is drawn as a color emoji even without a variation selector after it. Emoji
+/// outside the BMP mostly are, while the older ones inside it mostly default to text, apart from
+/// these.
+fn has_emoji_presentation(ch: char) -> bool {
+    if ch as u32 > 0xFFFF {
+        return true;
+    }
+    matches!(
+        ch,
+        '\u{231A}'..='\u{231B}'
+            | '\u{23E9}'..='\u{23EC}'
+            | '\u{23F0}'
+            | '\u{23F3}'
+            | '\u{25FD}'..='\u{25FE}'
+            | '\u{2614}'..='\u{2615}'
+            | '\u{2648}'..='\u{2653}'
+            | '\u{267F}'
+            | '\u{2693}'
+            | '\u{26A1}'
+            | '\u{26AA}'..='\u{26AB}'
+            | '\u{26BD}'..='\u{26BE}'
+            | '\u{26C4}'..='\u{26C5}'
+            | '\u{26CE}'
+            | '\u{26D4}'
+            | '\u{26EA}'
+            | '\u{26F2}'..='\u{26F3}'
+            | '\u{26F5}'
+            | '\u{26FA}'
+            | '\u{26FD}'
+            | '\u{2705}'
+            | '\u{270A}'..='\u{270B}'
+            | '\u{2728}'
+            | '\u{274C}'
+            | '\u{274E}'
+            | '\u{2753}'..='\u{2755}'
+            | '\u{2757}'
+            | '\u{2795}'..='\u{2797}'
+            | '\u{27B0}'
+            | '\u{27BF}'
+            | '\u{2B1B}'..='\u{2B1C}'
+            | '\u{2B50}'
+            | '\u{2B55}'
+    )
+}
+
+/// Finds the emoji that `text` starts with, following the sequences of UTS #51: flags, keycaps,
+/// and pictographs with an optional variation selector, skin tone and tags, joined by ZWJs.
+/// Returns `None` if `text` doesn't start with an emoji.
+pub fn sequence(text: &str) -> Option<Sequence> {
+    let first = text.chars().next()?;
+    if is_regional_indicator(first) {
+        let len = first.len_utf8();
+        let len = match text[len..].chars().next() {
+            Some(second) if is_regional_indicator(second) => len + second.len_utf8(),
+            _ => len,
+        };
+        return Some(Sequence { len, color: true });
+    }
+    if matches!(first, '0'..='9' | '#' | '*') {
+        let rest = &text[1..];
+        let rest = rest.strip_prefix(EMOJI_PRESENTATION).unwrap_or(rest);
+        let rest = rest.strip_prefix(KEYCAP)?;
+        return Some(Sequence {
+            len: text.len() - rest.len(),
+            color: true,
+        });
+    }
+    let mut sequence = element(text)?;
+    while let Some(rest) = text[sequence.len..].strip_prefix(ZWJ) {
+        let Some(next) = element(rest) else {
+            break;
+        };
+        sequence.len += ZWJ.len_utf8() + next.len;
+        sequence.color = true;
+    }
+    Some(sequence)
+}
+
+/// A single pictograph with whatever modifies it, which is one part of a ZWJ sequence
+fn element(text: &str) -> Option<Sequence> {
+    let mut chars = text.chars().peekable();
+    let base = chars.next()?;
+    if base.is_ascii() || !(base.is_extended_pictographic() || base.is_emoji()) {
+        return None;
+    }
+    let mut len = base.len_utf8();
+    let mut color = has_emoji_presentation(base);
+    if let Some(&selector @ (EMOJI_PRESENTATION | TEXT_PRESENTATION)) = chars.peek() {
+        len += selector.len_utf8();
+        color = selector == EMOJI_PRESENTATION;
+        chars.next();
+    }
+    if let Some(&modifier) = chars.peek().filter(|&&ch| is_modifier(ch)) {
+        len += modifier.len_utf8();
+        color = true;
+        chars.next();
+    }
+    if chars.peek().is_some_and(|&ch| is_tag(ch)) {
+        while let Some(&tag) = chars.peek().filter(|&&ch| is_tag(ch) || ch == CANCEL_TAG) {
+            len += tag.len_utf8();
+            chars.next();
+            if tag == CANCEL_TAG {
+                break;
+            }
+        }
+        color = true;
+    }
+    Some(Sequence { len, color })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences() {
+        // some text, the part of it that should be found as an emoji, and whether it's color
+        let cases: &[(&str, Option<(&str, bool)>)] = &[
+            ("a", None),
+            ("", None),
+            ("\u{1F600}x", Some(("\u{1F600}", true))),
+            // text by default, unless a variation selector says otherwise
+            ("\u{263A}", Some(("\u{263A}", false))),
+            ("\u{263A}\u{FE0F}x", Some(("\u{263A}\u{FE0F}", true))),
+            ("\u{231A}", Some(("\u{231A}", true))),
+            ("\u{231A}\u{FE0E}x", Some(("\u{231A}\u{FE0E}", false))),
+            // skin tones
+            ("\u{1F44D}\u{1F3FD}x", Some(("\u{1F44D}\u{1F3FD}", true))),
+            // ZWJ sequences, which stop at a joiner that nothing comes after
+            (
+                "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}x",
+                Some(("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}", true)),
+            ),
+            (
+                "\u{2764}\u{FE0F}\u{200D}\u{1F525}",
+                Some(("\u{2764}\u{FE0F}\u{200D}\u{1F525}", true)),
+            ),
+            ("\u{1F468}\u{200D}a", Some(("\u{1F468}", true))),
+            // keycaps, which need the combining keycap after the digit
+            ("1\u{FE0F}\u{20E3}x", Some(("1\u{FE0F}\u{20E3}", true))),
+            ("#\u{20E3}", Some(("#\u{20E3}", true))),
+            ("1", None),
+            ("1\u{FE0F}", None),
+            // flags, and a lone regional indicator
+            (
+                "\u{1F1EF}\u{1F1F5}\u{1F1EB}",
+                Some(("\u{1F1EF}\u{1F1F5}", true)),
+            ),
+            ("\u{1F1EF}x", Some(("\u{1F1EF}", true))),
+            // a subdivision flag, which is tag letters ended by the cancel tag
+            (
+                "\u{1F3F4}\u{E0067}\u{E0062}\u{E0065}\u{E006E}\u{E0067}\u{E007F}x",
+                Some((
+                    "\u{1F3F4}\u{E0067}\u{E0062}\u{E0065}\u{E006E}\u{E0067}\u{E007F}",
+                    true,
+                )),
+            ),
+        ];
+        for &(text, expected) in cases {
+            let found = sequence(text).map(|sequence| (&text[..sequence.len], sequence.color));
+            assert_eq!(found, expected, "in {text:?}");
+        }
+    }
+}
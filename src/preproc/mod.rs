@@ -1,78 +1,527 @@
-use crate::font::Font;
+use std::ops::Range;
 
-use self::atlas::AtlasID;
+use crate::font::{self, FontMetrics, FontStack, GlyphMode, MissingGlyph, RasterOptions};
+
+use self::{atlas::AtlasID, shape::Shaper, wrap::WrapGlyph};
 use anyhow::Result;
+use swash::text::{Category, Codepoint};
+use unicode_bidi::ParagraphBidiInfo;
+use unicode_segmentation::UnicodeSegmentation;
 
+mod align;
 mod atlas;
-mod text;
+mod bidi;
+mod emoji;
+mod packer;
+mod shape;
+pub mod text;
+mod wrap;
+
+pub use align::Align;
+pub use atlas::{
+    Atlas, AtlasFull, AtlasGlyph, AtlasKind, AtlasUpdates, AtlasView, DirtyRect, GlyphKey,
+    StyleFlags,
+};
+pub use packer::Occupancy;
+pub use shape::{GlyphRun, ShapedGlyph};
+pub use wrap::Wrap;
+
+/// How many spaces wide a tab is
+const TAB_SPACES: f32 = 4.;
 
-pub use atlas::{Atlas, AtlasView};
+/// A glyph in a line of preprocessed text, along with where the shaper placed it
+#[derive(Copy, Clone, Debug)]
+pub struct LineGlyph {
+    pub id: AtlasID,
+    pub advance_x: f32,
+    pub advance_y: f32,
+    pub offset_x: f32,
+    pub offset_y: f32,
+    /// the index in `Line::clusters` of the grapheme cluster the glyph draws. A ligature draws
+    /// several clusters, and points at the first of them.
+    pub cluster: u32,
+    /// whether the glyph is a space between words, which justified lines stretch
+    pub gap: bool,
+    /// how many times bigger than its atlas image the glyph is drawn, which is only ever not 1
+    /// for distance fields
+    pub scale: f32,
+}
+
+/// An extended grapheme cluster of the source text, which is what a cursor moves over and a
+/// selection covers
+#[derive(Clone, Debug)]
+pub struct Cluster {
+    /// the bytes of the cluster in the string that was added, counting earlier lines
+    pub range: Range<usize>,
+    /// the logical indices of the glyphs that draw the cluster, see `Line::logical_to_visual`.
+    /// This is empty for clusters that aren't drawn, like the later letters of a ligature, and
+    /// sits where the cluster's glyphs would be.
+    pub glyphs: Range<usize>,
+    /// whether the cluster is in right to left text, so that a cursor after it goes on its left
+    pub rtl: bool,
+}
 
 #[derive(Debug, Default)]
 pub struct Line {
-    pub glyphs: Vec<AtlasID>,
+    /// the glyphs in the order they're drawn, left to right
+    pub glyphs: Vec<LineGlyph>,
+    /// every grapheme cluster of the line's source in logical order
+    pub clusters: Vec<Cluster>,
+    /// the index in `glyphs` of each glyph in logical order
+    pub logical_to_visual: Vec<u32>,
+    /// the logical index of each glyph in `glyphs`
+    pub visual_to_logical: Vec<u32>,
+    /// whether the line's base direction is right to left, which is picked from its first
+    /// strong character
+    pub rtl: bool,
+    /// where the line is put, which is only ever left, right, center or justify
+    pub align: Align,
+    /// the width the line is aligned in, which is the wrap width, or that of the widest line added
+    /// with it if it isn't wrapped
+    pub width: f32,
+    /// where the line box starts, measured down from the top of the first line
+    pub top: f32,
+    /// the room the line needs above its baseline, which is the largest ascent of the fonts and
+    /// sizes in it
+    pub ascent: f32,
+    /// the room the line needs below its baseline
+    pub descent: f32,
+    /// the gap between this line and the next, half of which goes above the line and half below
+    pub leading: f32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Text {
     pub lines: Vec<Line>,
-    pub line_height: f32,
 }
 
-pub struct Preprocessor<'a> {
-    pub font: Font<'a>,
+/// Shapes text and stores its glyphs in an atlas. When the atlas is full, glyphs that weren't
+/// used this frame are evicted to make room, except for those in `text`. Any other `Text` that's
+/// still being drawn, like one taken out of `text` to be published, has to be passed to
+/// `Atlas::mark_used` every frame to keep its glyphs.
+pub struct Preprocessor {
+    pub fonts: FontStack,
     pub atlas: Atlas,
     pub text: Text,
+    /// the size that text is shaped and rasterized at, which can be changed between calls to
+    /// `add_text` to mix sizes in one atlas. Distance fields are only rasterized once, at
+    /// `GlyphMode::FIELD_SIZE`, and scaled to this size.
     pub point: f32,
+    /// how glyphs added from now on are rasterized. Changing it doesn't replace glyphs that are
+    /// already in the atlas.
+    pub mode: GlyphMode,
+    /// how coverage glyphs added from now on are rasterized
+    pub raster: RasterOptions,
+    /// what to draw for characters that no font has a glyph for
+    pub missing: MissingGlyph,
+    /// where lines of text added from now on are broken when they're wider than `wrap_width`
+    pub wrap: Wrap,
+    /// the width that lines are wrapped at and aligned in
+    pub wrap_width: f32,
+    /// how lines of text added from now on are aligned, unless their paragraph has its own
+    pub align: Align,
+    shaper: Shaper,
+}
+
+/// A shaped glyph once it's been decided what to draw for it, before it's rasterized
+#[derive(Copy, Clone, Debug)]
+struct Resolved {
+    /// what to draw, without a subpixel bin yet, since that depends on where the line is broken
+    key: GlyphKey,
+    glyph: ShapedGlyph,
+    /// how far the glyph moves the pen, which is that of whatever is drawn in its place if the
+    /// font doesn't have it
+    advance_x: f32,
+    /// the metrics of the font and size the glyph was shaped with
+    metrics: FontMetrics,
 }
 
 impl Text {
-    pub fn new(line_height: f32) -> Self {
-        Self {
-            lines: Vec::new(),
-            line_height,
-        }
+    /// How tall all of the lines are together, which is where the next line would start
+    pub fn height(&self) -> f32 {
+        self.lines
+            .last()
+            .map_or(0., |line| line.top + line.height())
+    }
+}
+
+impl Line {
+    /// The height of the line box, from the top of the line to the top of the next one
+    pub fn height(&self) -> f32 {
+        self.ascent + self.descent + self.leading
+    }
+
+    /// Where the line's glyphs sit, measured down from the top of the first line
+    pub fn baseline(&self) -> f32 {
+        self.top + self.leading / 2. + self.ascent
+    }
+
+    /// The top and bottom of a caret in the line, which spans the line's ascent and descent
+    /// without the leading
+    pub fn caret(&self) -> Range<f32> {
+        let baseline = self.baseline();
+        baseline - self.ascent..baseline + self.descent
+    }
+
+    /// The cluster that the byte at `index` of the source string is in
+    pub fn cluster_at(&self, index: usize) -> Option<&Cluster> {
+        let position = self
+            .clusters
+            .partition_point(|cluster| cluster.range.end <= index);
+        self.clusters
+            .get(position)
+            .filter(|cluster| cluster.range.contains(&index))
+    }
+
+    /// The bytes of the source string that the glyph at visual index `glyph` draws
+    pub fn glyph_source(&self, glyph: usize) -> Option<Range<usize>> {
+        let cluster = self.glyphs.get(glyph)?.cluster as usize;
+        Some(self.clusters.get(cluster)?.range.clone())
     }
 }
 
-impl<'a> Preprocessor<'a> {
-    pub fn new(font: Font<'a>, atlas: Atlas, point: f32) -> Self {
+impl Preprocessor {
+    pub fn new(fonts: impl Into<FontStack>, atlas: Atlas, point: f32) -> Self {
+        let fonts = fonts.into();
         Self {
-            text: Text::new(font.line_height(point)),
-            font,
+            text: Text::default(),
+            fonts,
             atlas,
             point,
+            mode: GlyphMode::Coverage,
+            raster: RasterOptions::default(),
+            missing: MissingGlyph::default(),
+            wrap: Wrap::default(),
+            wrap_width: f32::INFINITY,
+            align: Align::default(),
+            shaper: Shaper::default(),
+        }
+    }
+
+    /// Looks up or rasterizes the glyph for `key`, returning its id and how many times bigger than
+    /// its image it's drawn
+    fn glyph(&mut self, key: GlyphKey) -> Result<(AtlasID, f32)> {
+        let (key, scale) = key.stored();
+        if let Some(id) = self.atlas.use_glyph(key) {
+            return Ok((id, scale));
         }
+        let glyph = match key.hex_box {
+            Some(codepoint) => font::hex_box(codepoint, key.size()),
+            None => self.fonts.font(key.font_id).load_glyph(
+                key.glyph_id,
+                key.size(),
+                key.mode,
+                &key.raster,
+                key.raster.bin_offset(key.subpixel_bin),
+            )?,
+        };
+        let id = self.atlas.store_glyph_evicting(key, glyph, &[&self.text])?;
+        Ok((id, scale))
     }
 
-    fn codepoint(&mut self, codepoint: impl Into<u32>) -> Result<AtlasID> {
-        let codepoint = codepoint.into();
-        if let Some(id) = self.atlas.get_glyph_id(codepoint) {
-            return Ok(id);
+    /// Picks what `self.missing` says to draw in place of `source`, which either no font has a
+    /// glyph for or whose glyph couldn't be rendered, along with how far it moves the pen.
+    /// Returns `None` if nothing should be drawn.
+    fn missing_key(&mut self, key: GlyphKey, source: char) -> Option<(GlyphKey, f32)> {
+        let size = key.size();
+        match self.missing {
+            MissingGlyph::Notdef => Some((
+                GlyphKey { glyph_id: 0, ..key },
+                self.fonts.font(key.font_id).advance_width(0, size),
+            )),
+            MissingGlyph::Replacement(replacement) => {
+                let font_id = self.fonts.resolve(replacement);
+                let font = self.fonts.font(font_id);
+                let glyph_id = font.font_ref().charmap().map(replacement);
+                let key = GlyphKey {
+                    font_id,
+                    glyph_id,
+                    instance: font.instance_key(),
+                    style: font.synthesis().into(),
+                    ..key
+                };
+                Some((key, font.advance_width(glyph_id, size)))
+            }
+            MissingGlyph::HexBox => Some((
+                GlyphKey {
+                    hex_box: Some(source),
+                    ..key
+                },
+                font::hex_box_advance(source, size),
+            )),
+            MissingGlyph::Skip => None,
         }
-        let glyph = self.font.load_glyph(codepoint, self.point)?;
-        self.atlas.store_glyph(codepoint, glyph);
-        let id = self.atlas.get_glyph_id(codepoint).expect(&format!(
-            "Failed to look up codepoint {codepoint} from atlas"
-        ));
-        Ok(id)
+    }
+
+    /// Decides what to draw for a glyph shaped from `run`. Returns `None` if nothing should be
+    /// drawn.
+    fn resolve(
+        &mut self,
+        run: &GlyphRun,
+        metrics: FontMetrics,
+        glyph: ShapedGlyph,
+        source: char,
+    ) -> Option<Resolved> {
+        let font = self.fonts.font(run.font);
+        let key = GlyphKey {
+            mode: self.mode,
+            instance: font.instance_key(),
+            style: font.synthesis().into(),
+            raster: self.raster,
+            ..GlyphKey::new(run.font, glyph.id, run.point)
+        };
+        // tabs are shaped as spaces, since fonts rarely have a glyph for them
+        if source == '\t' {
+            return Some(Resolved {
+                key,
+                glyph,
+                advance_x: TAB_SPACES * glyph.advance_x,
+                metrics,
+            });
+        }
+        // glyph 0 is .notdef, which the shaper gives characters that no font covers
+        if glyph.id != 0 {
+            return Some(Resolved {
+                key,
+                glyph,
+                advance_x: glyph.advance_x,
+                metrics,
+            });
+        }
+        if is_invisible(source) {
+            return None;
+        }
+        let (key, advance_x) = self.missing_key(key, source)?;
+        Some(Resolved {
+            key,
+            glyph,
+            advance_x,
+            metrics,
+        })
     }
 
     pub fn add_text(&mut self, text: &text::Text) -> Result<()> {
         let mut lines = Vec::new();
         for line in &text.lines {
-            let glyphs = line
-                .text
-                .chars()
-                .map(|codepoint| self.codepoint(codepoint))
-                .collect::<Result<Vec<_>, _>>()?;
-            lines.push(Line { glyphs });
+            let bidi = ParagraphBidiInfo::new(&line.text, None);
+            let runs = self
+                .shaper
+                .shape_line(&mut self.fonts, line, &bidi.levels, self.point);
+            let mut resolved = Vec::new();
+            for run in runs {
+                let metrics = self.fonts.font(run.font).metrics(run.point);
+                for &glyph in &run.glyphs {
+                    let source = line.text[glyph.cluster as usize..]
+                        .chars()
+                        .next()
+                        .unwrap_or_default();
+                    resolved.extend(self.resolve(&run, metrics, glyph, source));
+                }
+            }
+            // every line is at least as tall as the primary font at the paragraph's size, even if
+            // it's empty or only has smaller text
+            let strut = self
+                .fonts
+                .primary()
+                .metrics(line.point_at(0).unwrap_or(self.point));
+            // lines are broken in logical order, before each one is reordered on its own
+            let wrap_glyphs: Vec<WrapGlyph> = resolved
+                .iter()
+                .map(|resolved| WrapGlyph {
+                    cluster: resolved.glyph.cluster,
+                    advance_x: resolved.advance_x,
+                })
+                .collect();
+            let wrapped = wrap::break_line(&line.text, &wrap_glyphs, self.wrap, self.wrap_width);
+            for (index, glyphs) in wrapped.iter().enumerate() {
+                // the bytes of the source line that the wrapped line covers
+                let start = match index {
+                    0 => 0,
+                    _ => resolved[glyphs.start].glyph.cluster as usize,
+                };
+                let end = wrapped
+                    .get(index + 1)
+                    .map(|next| resolved[next.start].glyph.cluster as usize)
+                    .unwrap_or(line.text.len());
+                let mut laid_out =
+                    self.lay_out_line(line, &bidi, &resolved[glyphs.clone()], start..end)?;
+                let align = line.align.unwrap_or(self.align);
+                laid_out.align = align.resolve(laid_out.rtl, index + 1 == wrapped.len());
+                laid_out.ascent = strut.ascent;
+                laid_out.descent = strut.descent;
+                laid_out.leading = strut.leading;
+                for resolved in &resolved[glyphs.clone()] {
+                    laid_out.ascent = laid_out.ascent.max(resolved.metrics.ascent);
+                    laid_out.descent = laid_out.descent.max(resolved.metrics.descent);
+                    laid_out.leading = laid_out.leading.max(resolved.metrics.leading);
+                }
+                lines.push(laid_out);
+            }
+        }
+        let width = match self.wrap_width {
+            width if width.is_finite() => width,
+            _ => lines
+                .iter()
+                .map(|line| line.glyphs.iter().map(|glyph| glyph.advance_x).sum())
+                .fold(0., f32::max),
+        };
+        let mut top = self.text.height();
+        for line in &mut lines {
+            line.width = width;
+            line.top = top;
+            top += line.height();
         }
         self.text.lines.extend(lines);
         Ok(())
     }
 
+    /// Reorders the glyphs of one wrapped line visually and rasterizes them, where `bytes` is the
+    /// part of `line` that they came from
+    fn lay_out_line(
+        &mut self,
+        line: &text::Line,
+        bidi: &ParagraphBidiInfo,
+        resolved: &[Resolved],
+        bytes: Range<usize>,
+    ) -> Result<Line> {
+        // the levels after trailing whitespace is put back at the paragraph's level
+        let levels = bidi.reordered_levels(bytes.clone());
+        let sources: Vec<u32> = resolved
+            .iter()
+            .map(|resolved| resolved.glyph.cluster)
+            .collect();
+        let order = bidi::visual_order(&sources, &levels);
+        // spaces that the line was broken after take up no room, so that they don't push it away
+        // from the edge it's aligned to
+        let hanging = match bytes.end == line.text.len() {
+            true => resolved.len(),
+            false => {
+                resolved.len()
+                    - resolved
+                        .iter()
+                        .rev()
+                        .take_while(|resolved| {
+                            line.text[resolved.glyph.cluster as usize..]
+                                .starts_with(char::is_whitespace)
+                        })
+                        .count()
+            }
+        };
+        let mut clusters: Vec<Cluster> = line.text[bytes.clone()]
+            .grapheme_indices(true)
+            .map(|(index, grapheme)| {
+                let start = line.offset + bytes.start + index;
+                Cluster {
+                    range: start..start + grapheme.len(),
+                    glyphs: 0..0,
+                    rtl: levels[bytes.start + index].is_rtl(),
+                }
+            })
+            .collect();
+        let mut glyphs = Vec::new();
+        // the index in `resolved` of each glyph that made it into `glyphs`
+        let mut logical = Vec::new();
+        // where the glyph's origin is along the line, for picking its subpixel bin
+        let mut pen = 0.;
+        for index in order {
+            let Resolved {
+                key,
+                glyph,
+                advance_x,
+                ..
+            } = resolved[index];
+            let source = line.text[glyph.cluster as usize..]
+                .chars()
+                .next()
+                .unwrap_or_default();
+            let subpixel_bin = match key.hex_box.is_some() || self.mode.is_field() {
+                true => 0,
+                false => self.raster.bin(pen + glyph.offset_x),
+            };
+            let key = GlyphKey {
+                subpixel_bin,
+                ..key
+            };
+            let ((id, scale), advance_x) = match self.glyph(key) {
+                Ok(glyph) => (glyph, advance_x),
+                Err(err) if could_not_render(&err) => {
+                    let Some((fallback, advance_x)) = self.missing_key(key, source) else {
+                        continue;
+                    };
+                    match self.glyph(fallback) {
+                        Ok(glyph) => (glyph, advance_x),
+                        // the fallback can't be drawn either, so leave the character out
+                        Err(err) if could_not_render(&err) => continue,
+                        Err(err) => return Err(err),
+                    }
+                }
+                Err(err) => return Err(err),
+            };
+            let advance_x = match index < hanging {
+                true => advance_x,
+                false => 0.,
+            };
+            pen += advance_x;
+            // the shaper's clusters can be bigger than a grapheme cluster, but always start at one
+            let byte = line.offset + glyph.cluster as usize;
+            let cluster = clusters.partition_point(|cluster| cluster.range.end <= byte);
+            glyphs.push(LineGlyph {
+                id,
+                advance_x,
+                advance_y: glyph.advance_y,
+                offset_x: glyph.offset_x,
+                offset_y: glyph.offset_y,
+                cluster: cluster as u32,
+                gap: source.is_whitespace() && advance_x > 0.,
+                scale,
+            });
+            logical.push(index);
+        }
+        let mut logical_to_visual: Vec<u32> = (0..glyphs.len() as u32).collect();
+        logical_to_visual.sort_by_key(|&visual| logical[visual as usize]);
+        let mut visual_to_logical = vec![0; glyphs.len()];
+        for (index, &visual) in logical_to_visual.iter().enumerate() {
+            visual_to_logical[visual as usize] = index as u32;
+            let cluster = &mut clusters[glyphs[visual as usize].cluster as usize];
+            if cluster.glyphs.is_empty() {
+                cluster.glyphs = index..index + 1;
+            } else {
+                cluster.glyphs.end = index + 1;
+            }
+        }
+        // clusters without glyphs sit right after the glyphs of the cluster before them
+        let mut end = 0;
+        for cluster in &mut clusters {
+            if cluster.glyphs.is_empty() {
+                cluster.glyphs = end..end;
+            }
+            end = cluster.glyphs.end;
+        }
+        Ok(Line {
+            glyphs,
+            clusters,
+            logical_to_visual,
+            visual_to_logical,
+            rtl: bidi.paragraph_level.is_rtl(),
+            ..Line::default()
+        })
+    }
+
     pub fn add_str(&mut self, str: impl Into<String>) -> Result<()> {
         self.add_text(&text::Text::from(str.into()))
     }
 }
+
+fn could_not_render(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<font::Error>(),
+        Some(font::Error::CouldNotRender(_))
+    )
+}
+
+/// Format characters, like zero width joiners, and variation selectors, which are never drawn, so
+/// there's nothing missing when a font doesn't have them
+fn is_invisible(ch: char) -> bool {
+    ch.category() == Category::Format
+        || matches!(ch, '\u{FE00}'..='\u{FE0F}' | '\u{E0100}'..='\u{E01EF}')
+}
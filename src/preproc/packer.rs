@@ -0,0 +1,396 @@
+use std::ops::Range;
+
+/// A row of the atlas that glyphs are placed into left to right
+#[derive(Clone, Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// where the next glyph on this shelf will start
+    next_x: u32,
+    /// spaces before `next_x` that were freed by `deallocate`, including their padding, sorted
+    /// and never touching each other
+    free: Vec<Range<u32>>,
+}
+
+impl Shelf {
+    fn new(y: u32, height: u32) -> Self {
+        Self {
+            y,
+            height,
+            next_x: 0,
+            free: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.next_x == 0
+    }
+
+    /// The pixels of the shelf that are taken by rectangles, including their padding
+    fn used_width(&self) -> u32 {
+        let freed: u32 = self.free.iter().map(|span| span.end - span.start).sum();
+        self.next_x - freed
+    }
+
+    /// The smallest freed space that `width` fits in, or the space after `next_x` if none of them
+    /// are big enough
+    fn find(&self, width: u32, shelf_width: u32) -> Option<Option<usize>> {
+        let reused = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, span)| span.end - span.start >= width)
+            .min_by_key(|(_, span)| span.end - span.start)
+            .map(|(index, _)| index);
+        match reused {
+            Some(index) => Some(Some(index)),
+            None if shelf_width - self.next_x >= width => Some(None),
+            None => None,
+        }
+    }
+
+    /// Takes `width` from the start of the space that `find` returned, leaving the rest of it free
+    fn take(&mut self, found: Option<usize>, width: u32) -> u32 {
+        let Some(index) = found else {
+            self.next_x += width;
+            return self.next_x - width;
+        };
+        let span = &mut self.free[index];
+        let x = span.start;
+        span.start += width;
+        if span.start == span.end {
+            self.free.remove(index);
+        }
+        x
+    }
+
+    /// Frees `x..x + width`, merging it into the freed spaces next to it, and moving `next_x` back
+    /// over it if nothing comes after it
+    fn release(&mut self, x: u32, width: u32) {
+        let mut span = x..x + width;
+        let index = self.free.partition_point(|free| free.end <= span.start);
+        if index < self.free.len() && self.free[index].start == span.end {
+            span.end = self.free.remove(index).end;
+        }
+        if index > 0 && self.free[index - 1].end == span.start {
+            span.start = self.free.remove(index - 1).start;
+        }
+        if span.end == self.next_x {
+            self.next_x = span.start;
+        } else {
+            let index = self.free.partition_point(|free| free.end <= span.start);
+            self.free.insert(index, span);
+        }
+    }
+}
+
+/// A shelf packer, which places rectangles on the shortest shelf that they fit on and opens a new
+/// shelf below the others when none of them have room
+#[derive(Clone, Debug)]
+pub struct ShelfPacker {
+    width: u32,
+    height: u32,
+    padding: u32,
+    shelves: Vec<Shelf>,
+    /// the pixels covered by packed rectangles, not counting padding
+    used_area: u64,
+    rects: usize,
+}
+
+/// How full a packer is
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Occupancy {
+    /// how many rectangles have been packed
+    pub rects: usize,
+    /// the pixels covered by packed rectangles, not counting padding
+    pub used_area: u64,
+    /// the pixels taken up by rectangles on shelves, including padding and the space wasted above
+    /// short glyphs
+    pub shelf_area: u64,
+    pub total_area: u64,
+}
+
+impl ShelfPacker {
+    pub fn new(width: u32, height: u32, padding: u32) -> Self {
+        Self {
+            width,
+            height,
+            padding,
+            shelves: Vec::new(),
+            used_area: 0,
+            rects: 0,
+        }
+    }
+
+    /// Finds room for a `width` by `height` rectangle, returning its top left corner, or `None` if
+    /// the packer is full
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width == 0 || height == 0 {
+            return Some((0, 0));
+        }
+        let (padded_width, padded_height) = (width + self.padding, height + self.padding);
+        let (index, found) = self.find(padded_width, padded_height)?;
+        let shelf = match index {
+            Some(index) => &mut self.shelves[index],
+            None => {
+                self.shelves.push(Shelf::new(self.next_y(), padded_height));
+                self.shelves.last_mut().unwrap()
+            }
+        };
+        // an empty shelf that is much taller than the rectangle, which is left by merging shelves
+        // that were emptied, gives what the rectangle doesn't need to a new shelf below it
+        let split = (shelf.is_empty() && shelf.height > padded_height)
+            .then(|| Shelf::new(shelf.y + padded_height, shelf.height - padded_height));
+        if split.is_some() {
+            shelf.height = padded_height;
+        }
+        let position = (shelf.take(found, padded_width), shelf.y);
+        if let (Some(split), Some(index)) = (split, index) {
+            self.shelves.insert(index + 1, split);
+        }
+        self.used_area += width as u64 * height as u64;
+        self.rects += 1;
+        Some(position)
+    }
+
+    /// Whether `allocate` would find room for a `width` by `height` rectangle
+    pub fn can_allocate(&self, width: u32, height: u32) -> bool {
+        width == 0
+            || height == 0
+            || self
+                .find(width + self.padding, height + self.padding)
+                .is_some()
+    }
+
+    /// The shortest shelf with room for a padded rectangle and where on it the rectangle goes, or
+    /// `None` for the shelf if a new one has to be opened for it
+    fn find(&self, width: u32, height: u32) -> Option<(Option<usize>, Option<usize>)> {
+        if width > self.width {
+            return None;
+        }
+        let found = self
+            .shelves
+            .iter()
+            .enumerate()
+            .filter(|(_, shelf)| shelf.height >= height)
+            .filter_map(|(index, shelf)| {
+                Some((index, shelf.height, shelf.find(width, self.width)?))
+            })
+            .min_by_key(|&(_, height, _)| height);
+        match found {
+            Some((index, _, found)) => Some((Some(index), found)),
+            None if self.next_y() + height <= self.height => Some((None, None)),
+            None => None,
+        }
+    }
+
+    /// Where a new shelf would start
+    fn next_y(&self) -> u32 {
+        self.shelves
+            .last()
+            .map(|shelf| shelf.y + shelf.height)
+            .unwrap_or(0)
+    }
+
+    /// Frees the space of a `width` by `height` rectangle that was allocated at `x`, `y`, so that
+    /// it can be reused by later rectangles. Freed space is merged with the free space next to it,
+    /// and shelves that are emptied are merged with the empty shelves around them, so that bigger
+    /// rectangles than the ones freed can fit.
+    pub fn deallocate(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let Some(index) = self.shelves.iter().position(|shelf| shelf.y == y) else {
+            return;
+        };
+        self.shelves[index].release(x, width + self.padding);
+        self.used_area -= width as u64 * height as u64;
+        self.rects -= 1;
+
+        if !self.shelves[index].is_empty() {
+            return;
+        }
+        let mut start = index;
+        while start > 0 && self.shelves[start - 1].is_empty() {
+            start -= 1;
+        }
+        let mut end = index + 1;
+        while end < self.shelves.len() && self.shelves[end].is_empty() {
+            end += 1;
+        }
+        if end == self.shelves.len() {
+            // nothing is below, so the space goes back to the part of the page without shelves
+            self.shelves.truncate(start);
+        } else {
+            let height = self.shelves[start..end]
+                .iter()
+                .map(|shelf| shelf.height)
+                .sum();
+            self.shelves[start].height = height;
+            self.shelves.drain(start + 1..end);
+        }
+    }
+
+    pub fn occupancy(&self) -> Occupancy {
+        Occupancy {
+            rects: self.rects,
+            used_area: self.used_area,
+            shelf_area: self
+                .shelves
+                .iter()
+                .map(|shelf| shelf.used_width() as u64 * shelf.height as u64)
+                .sum(),
+            total_area: self.width as u64 * self.height as u64,
+        }
+    }
+}
+
+impl Occupancy {
+    /// The fraction of the total area covered by packed rectangles
+    pub fn fraction(&self) -> f32 {
+        if self.total_area == 0 {
+            return 0.;
+        }
+        self.used_area as f32 / self.total_area as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freed_space_fits_a_glyph_after_eviction() {
+        let mut packer = ShelfPacker::new(32, 8, 0);
+        let first = packer.allocate(16, 8).unwrap();
+        packer.allocate(16, 8).unwrap();
+        assert_eq!(packer.allocate(8, 8), None);
+
+        packer.deallocate(first.0, first.1, 16, 8);
+        assert!(packer.can_allocate(8, 8));
+        assert_eq!(packer.allocate(8, 8), Some(first));
+        // the rest of the freed space is still free
+        assert_eq!(packer.allocate(8, 8), Some((8, 0)));
+        assert_eq!(packer.allocate(1, 1), None);
+    }
+
+    #[test]
+    fn neighbouring_freed_spaces_fit_a_wider_glyph() {
+        let mut packer = ShelfPacker::new(32, 8, 0);
+        let rects: Vec<_> = (0..4).map(|_| packer.allocate(8, 8).unwrap()).collect();
+        assert!(!packer.can_allocate(16, 8));
+
+        packer.deallocate(rects[1].0, rects[1].1, 8, 8);
+        assert!(!packer.can_allocate(16, 8));
+        packer.deallocate(rects[2].0, rects[2].1, 8, 8);
+        assert_eq!(packer.allocate(16, 8), Some((8, 0)));
+    }
+
+    #[test]
+    fn emptied_shelves_fit_a_taller_glyph() {
+        let mut packer = ShelfPacker::new(16, 16, 0);
+        let rects: Vec<_> = (0..8).map(|_| packer.allocate(8, 4).unwrap()).collect();
+        assert!(!packer.can_allocate(8, 8));
+
+        // emptying the two middle shelves leaves one shelf tall enough
+        for &(x, y) in &rects[2..6] {
+            packer.deallocate(x, y, 8, 4);
+        }
+        assert_eq!(packer.allocate(16, 8), Some((0, 4)));
+        assert_eq!(packer.allocate(1, 1), None);
+    }
+
+    #[test]
+    fn emptied_last_shelf_goes_back_to_the_page() {
+        let mut packer = ShelfPacker::new(16, 16, 0);
+        packer.allocate(16, 4).unwrap();
+        let short = packer.allocate(16, 4).unwrap();
+        packer.deallocate(short.0, short.1, 16, 4);
+        // a shelf 4 pixels tall would be left above the new one if it wasn't given back
+        assert_eq!(packer.allocate(16, 12), Some((0, 4)));
+    }
+
+    #[test]
+    fn rectangles_go_on_the_shortest_shelf_they_fit_on() {
+        let mut packer = ShelfPacker::new(12, 32, 0);
+        assert_eq!(packer.allocate(4, 8), Some((0, 0)));
+        assert_eq!(packer.allocate(4, 4), Some((4, 0)));
+        // too wide for what is left of the first shelf
+        assert_eq!(packer.allocate(8, 4), Some((0, 8)));
+        assert_eq!(packer.allocate(4, 2), Some((8, 8)));
+        assert_eq!(packer.allocate(4, 6), Some((8, 0)));
+        // too tall for either shelf
+        assert_eq!(packer.allocate(4, 9), Some((0, 12)));
+    }
+
+    #[test]
+    fn padding_is_left_after_each_rectangle() {
+        let mut packer = ShelfPacker::new(16, 16, 2);
+        assert_eq!(packer.allocate(4, 4), Some((0, 0)));
+        assert_eq!(packer.allocate(4, 4), Some((6, 0)));
+        assert_eq!(packer.allocate(4, 5), Some((0, 6)));
+        // the padding has to fit on the page too
+        assert_eq!(packer.allocate(15, 1), None);
+        assert!(!packer.can_allocate(15, 1));
+    }
+
+    #[test]
+    fn full_packer_has_no_room() {
+        let mut packer = ShelfPacker::new(8, 8, 0);
+        for _ in 0..4 {
+            assert!(packer.allocate(4, 4).is_some());
+        }
+        assert!(!packer.can_allocate(1, 1));
+        assert_eq!(packer.allocate(1, 1), None);
+        assert_eq!(packer.allocate(9, 1), None);
+        // empty rectangles never take any room
+        assert_eq!(packer.allocate(0, 4), Some((0, 0)));
+    }
+
+    #[test]
+    fn occupancy_counts_shelves_and_rectangles() {
+        let mut packer = ShelfPacker::new(16, 16, 1);
+        assert_eq!(packer.occupancy().fraction(), 0.);
+        packer.allocate(3, 3).unwrap();
+        packer.allocate(5, 2).unwrap();
+        packer.allocate(4, 6).unwrap();
+        assert_eq!(
+            packer.occupancy(),
+            Occupancy {
+                rects: 3,
+                used_area: 9 + 10 + 24,
+                shelf_area: (4 + 6) * 4 + 5 * 7,
+                total_area: 256,
+            }
+        );
+    }
+
+    #[test]
+    fn occupancy_follows_allocation_and_freeing() {
+        let mut packer = ShelfPacker::new(16, 16, 1);
+        let first = packer.allocate(3, 3).unwrap();
+        packer.allocate(5, 2).unwrap();
+        assert_eq!(
+            packer.occupancy(),
+            Occupancy {
+                rects: 2,
+                used_area: 9 + 10,
+                shelf_area: (4 + 6) * 4,
+                total_area: 256,
+            }
+        );
+
+        packer.deallocate(first.0, first.1, 3, 3);
+        let occupancy = packer.occupancy();
+        assert_eq!(occupancy.rects, 1);
+        assert_eq!(occupancy.used_area, 10);
+        assert_eq!(occupancy.shelf_area, 6 * 4);
+
+        packer.allocate(2, 2).unwrap();
+        let occupancy = packer.occupancy();
+        assert_eq!(occupancy.rects, 2);
+        assert_eq!(occupancy.used_area, 10 + 4);
+        assert_eq!(occupancy.shelf_area, (3 + 6) * 4);
+        assert_eq!(occupancy.fraction(), 14. / 256.);
+    }
+}
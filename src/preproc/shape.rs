@@ -0,0 +1,177 @@
+use swash::{
+    shape::{Direction, ShapeContext},
+    text::{Codepoint, Script},
+    GlyphId, Setting,
+};
+use unicode_bidi::Level;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::font::{FontId, FontStack};
+
+use super::{emoji, is_invisible, text::Line};
+
+/// A single glyph as positioned by the shaper
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ShapedGlyph {
+    pub id: GlyphId,
+    /// the byte offset in the source line of the start of the cluster this glyph belongs to
+    pub cluster: u32,
+    /// how much to move the pen after drawing the glyph, including kerning
+    pub advance_x: f32,
+    pub advance_y: f32,
+    /// how far to shift the glyph from the pen position, used to attach marks
+    pub offset_x: f32,
+    pub offset_y: f32,
+}
+
+/// A sequence of glyphs that were all shaped with the same font, size and script
+#[derive(Clone, Debug)]
+pub struct GlyphRun {
+    pub font: FontId,
+    pub point: f32,
+    pub glyphs: Vec<ShapedGlyph>,
+}
+
+#[derive(Default)]
+pub struct Shaper {
+    context: ShapeContext,
+}
+
+/// Scripts that don't start a new run on their own, since they take on the script of the text
+/// around them
+fn is_neutral(script: Script) -> bool {
+    matches!(script, Script::Common | Script::Inherited | Script::Unknown)
+}
+
+impl Shaper {
+    /// Shapes a line in logical order, where `levels` has the bidi embedding level of each byte
+    /// of the line. Right to left runs come out in logical order too, and are only reversed when
+    /// the line is reordered. `point` is the size of text that no span gives a size to.
+    pub fn shape_line(
+        &mut self,
+        fonts: &mut FontStack,
+        line: &Line,
+        levels: &[Level],
+        point: f32,
+    ) -> Vec<GlyphRun> {
+        let mut runs = Vec::new();
+        for item in itemize(fonts, line, levels) {
+            let face = fonts.font(item.font);
+            let point = line.point_at(item.start).unwrap_or(point);
+            // features set on spans go after the font's own, so that they win
+            let features: Vec<Setting<u16>> = face
+                .features()
+                .iter()
+                .copied()
+                .chain(line.features_at(item.start))
+                .collect();
+            let mut shaper = self
+                .context
+                .builder(face.font_ref())
+                .script(item.script)
+                .direction(if item.level.is_rtl() {
+                    Direction::RightToLeft
+                } else {
+                    Direction::LeftToRight
+                })
+                .size(point)
+                .normalized_coords(face.coords())
+                .features(features)
+                .build();
+            // tabs are shaped as spaces, which every font has, and widened when they're resolved
+            shaper.add_str(&line.text[item.start..item.end].replace('\t', " "));
+            let mut glyphs = Vec::new();
+            // a synthetic bold is wider than the face it was made from
+            let embolden = face.synthesis().embolden(point);
+            shaper.shape_with(|cluster| {
+                for glyph in cluster.glyphs {
+                    glyphs.push(ShapedGlyph {
+                        id: glyph.id,
+                        cluster: item.start as u32 + cluster.source.start,
+                        advance_x: if glyph.advance != 0. {
+                            glyph.advance + embolden
+                        } else {
+                            0.
+                        },
+                        advance_y: 0.,
+                        offset_x: glyph.x,
+                        offset_y: glyph.y,
+                    });
+                }
+            });
+            runs.push(GlyphRun {
+                font: item.font,
+                point,
+                glyphs,
+            });
+        }
+        runs
+    }
+}
+
+/// A byte range of a line that can be shaped with a single font, script, direction and set of
+/// features
+struct Item {
+    start: usize,
+    end: usize,
+    font: FontId,
+    script: Script,
+    level: Level,
+    /// whether the item is made of color emoji, which are never shaped together with the text
+    /// around them, so that an emoji font doesn't also get used for the digits or spaces it covers
+    emoji: bool,
+}
+
+/// Splits a line into items. The line is walked a grapheme cluster at a time, so that an accent
+/// is never shaped apart from the letter it sits on, even if the font doesn't have it.
+fn itemize(fonts: &mut FontStack, line: &Line, levels: &[Level]) -> Vec<Item> {
+    let mut items: Vec<Item> = Vec::new();
+    for (index, grapheme) in line.text.grapheme_indices(true) {
+        let end = index + grapheme.len();
+        let ch = grapheme.chars().next().unwrap_or_default();
+        let level = levels[index];
+        // items never cross a span or a change of direction
+        let boundary = |item: &Item| line.is_span_boundary(index) || item.level != level;
+        if emoji::sequence(grapheme).is_some_and(|emoji| emoji.color) {
+            let font = fonts.resolve_emoji(ch);
+            match items.last_mut() {
+                Some(item) if item.emoji && item.font == font && !boundary(item) => {
+                    item.end = end;
+                }
+                _ => items.push(Item {
+                    start: index,
+                    end,
+                    font,
+                    script: Script::Common,
+                    level,
+                    emoji: true,
+                }),
+            }
+            continue;
+        }
+        let script = ch.script();
+        if let Some(item) = items.last_mut().filter(|item| !item.emoji) {
+            let same_script =
+                is_neutral(script) || is_neutral(item.script) || script == item.script;
+            // joiners and variation selectors stay with the text they change, even if the font
+            // doesn't have them, and tabs are shaped as spaces
+            let covered = fonts.font(item.font).covers(ch) || is_invisible(ch) || ch == '\t';
+            if same_script && covered && !boundary(item) {
+                item.end = end;
+                if is_neutral(item.script) {
+                    item.script = script;
+                }
+                continue;
+            }
+        }
+        items.push(Item {
+            start: index,
+            end,
+            font: fonts.resolve(ch),
+            script,
+            level,
+            emoji: false,
+        });
+    }
+    items
+}
@@ -1,3 +1,9 @@
+use std::ops::Range;
+
+use swash::Setting;
+
+use super::Align;
+
 #[derive(Debug)]
 pub struct Text {
     pub lines: Vec<Line>,
@@ -6,14 +12,117 @@ pub struct Text {
 #[derive(Debug)]
 pub struct Line {
     pub text: String,
+    /// where the line starts in the string that the text was split from, in bytes
+    pub offset: usize,
+    /// ranges of the line with their own OpenType features, where later spans win over earlier
+    /// ones that overlap them
+    pub spans: Vec<Span>,
+    /// how the line is aligned, instead of `Preprocessor::align`
+    pub align: Option<Align>,
+}
+
+/// A byte range of a line that's shaped with some OpenType features turned on or off, on top of
+/// the features of the font, or at its own size
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub range: Range<usize>,
+    /// features like `("tnum", 1)` or `("liga", 0)`
+    pub features: Vec<Setting<u16>>,
+    /// the size the span is shaped and rasterized at, instead of `Preprocessor::point`
+    pub point: Option<f32>,
+}
+
+impl Text {
+    /// Sets OpenType features for a byte range of the text that the lines were split from. The
+    /// range can cross lines.
+    pub fn with_features<I>(self, range: Range<usize>, features: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Setting<u16>>,
+    {
+        let features: Vec<Setting<u16>> = features.into_iter().map(Into::into).collect();
+        self.push_span(range, features, None)
+    }
+
+    /// Sets the size of a byte range of the text that the lines were split from. Lines that mix
+    /// sizes are made tall enough for the largest of them.
+    pub fn with_point(self, range: Range<usize>, point: f32) -> Self {
+        self.push_span(range, Vec::new(), Some(point))
+    }
+
+    /// Adds a span to every line that `range` crosses, cut to the part of the range in the line
+    fn push_span(
+        mut self,
+        range: Range<usize>,
+        features: Vec<Setting<u16>>,
+        point: Option<f32>,
+    ) -> Self {
+        for line in &mut self.lines {
+            let start = range.start.max(line.offset);
+            let end = range.end.min(line.offset + line.text.len());
+            if start < end {
+                line.spans.push(Span {
+                    range: start - line.offset..end - line.offset,
+                    features: features.clone(),
+                    point,
+                });
+            }
+        }
+        self
+    }
+
+    /// Sets the alignment of every line that a byte range of the text that the lines were split
+    /// from touches
+    pub fn with_align(mut self, range: Range<usize>, align: Align) -> Self {
+        for line in &mut self.lines {
+            if range.start <= line.offset + line.text.len() && range.end >= line.offset {
+                line.align = Some(align);
+            }
+        }
+        self
+    }
+}
+
+impl Line {
+    /// The features of every span that covers the byte at `index`, in the order they apply
+    pub fn features_at(&self, index: usize) -> impl Iterator<Item = Setting<u16>> + '_ {
+        self.spans
+            .iter()
+            .filter(move |span| span.range.contains(&index))
+            .flat_map(|span| span.features.iter().copied())
+    }
+
+    /// The size of the last span that covers the byte at `index` and sets one
+    pub fn point_at(&self, index: usize) -> Option<f32> {
+        self.spans
+            .iter()
+            .rev()
+            .filter(|span| span.range.contains(&index))
+            .find_map(|span| span.point)
+    }
+
+    /// Whether a span starts or ends at the byte at `index`
+    pub fn is_span_boundary(&self, index: usize) -> bool {
+        self.spans
+            .iter()
+            .any(|span| span.range.start == index || span.range.end == index)
+    }
 }
 
 impl From<String> for Text {
     fn from(value: String) -> Self {
         let lines = value
             .split("\n")
-            .map(|line| Line {
-                text: line.to_owned(),
+            .scan(0, |offset, line| {
+                let start = *offset;
+                // the newline between two lines isn't part of either of them
+                *offset += line.len() + 1;
+                Some(Line {
+                    text: line.to_owned(),
+                    offset: start,
+                    spans: Vec::new(),
+                    align: None,
+                })
             })
             .collect();
         Self { lines }
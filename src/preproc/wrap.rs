@@ -0,0 +1,161 @@
+use std::{iter, ops::Range};
+
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Where lines that are wider than `Preprocessor::wrap_width` are broken
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    /// lines are only broken at newlines, however wide they get
+    #[default]
+    None,
+    /// between words, at the line break opportunities of UAX #14. A word that's wider than the
+    /// whole line is broken between its grapheme clusters.
+    Word,
+    /// between any two grapheme clusters
+    Char,
+}
+
+/// A glyph of a line in logical order, as far as breaking the line is concerned
+#[derive(Copy, Clone, Debug)]
+pub struct WrapGlyph {
+    /// the byte offset in the line of the cluster the glyph belongs to
+    pub cluster: u32,
+    pub advance_x: f32,
+}
+
+/// Splits a line into the glyphs of each wrapped line, where `glyphs` are in logical order.
+/// Spaces at the end of a wrapped line hang past `width` instead of being moved to the next line.
+/// Always returns at least one range, even for a line without any glyphs.
+pub fn break_line(text: &str, glyphs: &[WrapGlyph], wrap: Wrap, width: f32) -> Vec<Range<usize>> {
+    // the byte offsets that a new line can start at, and whether it has to
+    let mut breaks: Vec<(usize, bool)> = match wrap {
+        Wrap::None => return iter::once(0..glyphs.len()).collect(),
+        Wrap::Word => linebreaks(text)
+            .map(|(index, opportunity)| (index, opportunity == BreakOpportunity::Mandatory))
+            .collect(),
+        Wrap::Char => text
+            .grapheme_indices(true)
+            .map(|(index, _)| (index, false))
+            .collect(),
+    };
+    breaks.retain(|&(index, _)| index > 0 && index < text.len());
+    let clusters: Vec<usize> = text
+        .grapheme_indices(true)
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut line_width = 0.;
+    // the last glyph that the line can be broken before, and the last one that starts a grapheme
+    // cluster, for words that are too wide to fit on a line of their own
+    let mut last_break = None;
+    let mut last_cluster = None;
+    for (index, glyph) in glyphs.iter().enumerate() {
+        let cluster = glyph.cluster as usize;
+        if index > start && glyphs[index - 1].cluster != glyph.cluster {
+            match breaks.binary_search_by_key(&cluster, |&(index, _)| index) {
+                Ok(position) if breaks[position].1 => {
+                    lines.push(start..index);
+                    start = index;
+                    line_width = 0.;
+                    last_break = None;
+                    last_cluster = None;
+                }
+                Ok(_) => last_break = Some(index),
+                Err(_) => {}
+            }
+            if index > start && clusters.binary_search(&cluster).is_ok() {
+                last_cluster = Some(index);
+            }
+        }
+        let hangs = text[cluster..].starts_with(char::is_whitespace);
+        if line_width + glyph.advance_x > width && !hangs {
+            if let Some(at) = last_break.or(last_cluster) {
+                lines.push(start..at);
+                start = at;
+                line_width = glyphs[at..index].iter().map(|glyph| glyph.advance_x).sum();
+                last_break = None;
+                last_cluster = last_cluster.filter(|&cluster| cluster > at);
+            }
+        }
+        line_width += glyph.advance_x;
+    }
+    lines.push(start..glyphs.len());
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One glyph a pixel wide for every character of `text`
+    fn glyphs(text: &str) -> Vec<WrapGlyph> {
+        text.char_indices()
+            .map(|(index, _)| WrapGlyph {
+                cluster: index as u32,
+                advance_x: 1.,
+            })
+            .collect()
+    }
+
+    fn lines(text: &str, wrap: Wrap, width: f32) -> Vec<Range<usize>> {
+        break_line(text, &glyphs(text), wrap, width)
+    }
+
+    #[test]
+    fn words_move_to_the_next_line() {
+        assert_eq!(lines("aaa bbb ccc", Wrap::Word, 6.), [0..4, 4..8, 8..11]);
+        assert_eq!(lines("aaa bbb ccc", Wrap::Word, 7.), [0..8, 8..11]);
+    }
+
+    #[test]
+    fn overlong_words_break_between_graphemes() {
+        assert_eq!(
+            lines("abcdefgh ij", Wrap::Word, 3.),
+            [0..3, 3..6, 6..9, 9..11]
+        );
+    }
+
+    #[test]
+    fn overlong_words_keep_graphemes_together() {
+        // an e with a combining acute accent, which is one grapheme of two characters
+        let text = "e\u{301}e\u{301}e\u{301}";
+        assert_eq!(lines(text, Wrap::Word, 3.), [0..2, 2..4, 4..6]);
+        assert_eq!(lines(text, Wrap::Char, 3.), [0..2, 2..4, 4..6]);
+    }
+
+    #[test]
+    fn trailing_spaces_hang() {
+        assert_eq!(lines("ab   cd", Wrap::Word, 2.), [0..5, 5..7]);
+        // even when there's no word after them
+        assert_eq!(
+            lines("ab   ", Wrap::Word, 2.),
+            iter::once(0..5).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn mandatory_breaks_always_break() {
+        // a line separator, which is three bytes long
+        assert_eq!(lines("ab\u{2028}cd", Wrap::Word, 100.), [0..3, 3..5]);
+    }
+
+    #[test]
+    fn char_wrap_breaks_anywhere() {
+        assert_eq!(lines("abc def", Wrap::Char, 2.), [0..2, 2..4, 4..6, 6..7]);
+    }
+
+    #[test]
+    fn no_wrap_keeps_one_line() {
+        assert_eq!(
+            lines("aaa bbb ccc", Wrap::None, 1.),
+            iter::once(0..11).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            lines("", Wrap::Word, 1.),
+            iter::once(0..0).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Checks the visual order of preprocessed lines that mix directions, and the maps between the
+//! visual and logical order of their glyphs.
+
+use titan_text::{
+    font::{self, FontStack, Selector},
+    preproc,
+};
+
+fn lay_out(text: &str) -> Option<preproc::Line> {
+    let Ok(font) = font::Loader::system().load_face(Selector::Family("DejaVu Sans")) else {
+        eprintln!("skipping, DejaVu Sans isn't installed");
+        return None;
+    };
+    let mut proc =
+        preproc::Preprocessor::new(FontStack::new(font), preproc::Atlas::new(256, 256), 16.);
+    proc.add_str(text).unwrap();
+    proc.text.lines.pop()
+}
+
+/// The grapheme cluster that each glyph of the line draws, in visual order
+fn visual_clusters(line: &preproc::Line) -> Vec<u32> {
+    line.glyphs.iter().map(|glyph| glyph.cluster).collect()
+}
+
+/// Checks that the two maps undo each other, and that they put the glyphs back in logical order
+fn check_maps(line: &preproc::Line) {
+    let len = line.glyphs.len();
+    assert_eq!(line.logical_to_visual.len(), len);
+    assert_eq!(line.visual_to_logical.len(), len);
+    for logical in 0..len {
+        let visual = line.logical_to_visual[logical] as usize;
+        assert_eq!(line.visual_to_logical[visual] as usize, logical);
+    }
+    let logical_clusters: Vec<u32> = line
+        .logical_to_visual
+        .iter()
+        .map(|&visual| line.glyphs[visual as usize].cluster)
+        .collect();
+    assert!(logical_clusters.windows(2).all(|pair| pair[0] <= pair[1]));
+    for (index, cluster) in line.clusters.iter().enumerate() {
+        for logical in cluster.glyphs.clone() {
+            assert_eq!(logical_clusters[logical], index as u32);
+        }
+    }
+}
+
+#[test]
+fn right_to_left_run_in_left_to_right_line() {
+    let Some(line) = lay_out("ab \u{5D0}\u{5D1}\u{5D2} cd") else {
+        return;
+    };
+    assert!(!line.rtl);
+    assert_eq!(visual_clusters(&line), [0, 1, 2, 5, 4, 3, 6, 7, 8]);
+    assert_eq!(line.logical_to_visual, [0, 1, 2, 5, 4, 3, 6, 7, 8]);
+    assert!(line.clusters[4].rtl && !line.clusters[0].rtl);
+    check_maps(&line);
+}
+
+#[test]
+fn left_to_right_run_in_right_to_left_line() {
+    let Some(line) = lay_out("\u{5D0}\u{5D1} ab \u{5D2}") else {
+        return;
+    };
+    assert!(line.rtl);
+    assert_eq!(visual_clusters(&line), [6, 5, 3, 4, 2, 1, 0]);
+    assert_eq!(line.visual_to_logical, [6, 5, 3, 4, 2, 1, 0]);
+    check_maps(&line);
+}
+
+#[test]
+fn marks_stay_after_their_base_in_right_to_left_text() {
+    // alef and bet, each with a point above
+    let Some(line) = lay_out("\u{5D0}\u{5B8}\u{5D1}\u{5B8}") else {
+        return;
+    };
+    assert_eq!(line.clusters.len(), 2);
+    assert_eq!(visual_clusters(&line), [1, 1, 0, 0]);
+    // the glyphs of each cluster keep their logical order, base first
+    assert_eq!(line.logical_to_visual, [2, 3, 0, 1]);
+    check_maps(&line);
+}
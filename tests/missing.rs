@@ -0,0 +1,148 @@
+//! Checks what is drawn for characters that the fonts don't have, and that the fonts of a stack
+//! are tried in order before falling back to the system fonts.
+
+use titan_text::{
+    font::{self, FontStack, MissingGlyph, Selector},
+    preproc::{self, GlyphKey},
+};
+
+/// a private use character, which none of the usual system fonts have
+const UNCOVERED: char = '\u{10FFFD}';
+
+fn load(family: &str) -> Option<font::Font> {
+    font::Loader::system()
+        .load_face(Selector::Family(family))
+        .ok()
+}
+
+fn preprocessor(missing: MissingGlyph) -> Option<preproc::Preprocessor> {
+    let Some(font) = load("DejaVu Sans") else {
+        eprintln!("skipping, DejaVu Sans isn't installed");
+        return None;
+    };
+    let mut proc =
+        preproc::Preprocessor::new(FontStack::new(font), preproc::Atlas::new(256, 256), 16.);
+    proc.missing = missing;
+    Some(proc)
+}
+
+/// The atlas key and advance of every glyph of the first line
+fn glyphs(proc: &preproc::Preprocessor) -> Vec<(GlyphKey, f32)> {
+    let entries = proc.atlas.as_atlas_view().entries;
+    proc.text.lines[0]
+        .glyphs
+        .iter()
+        .map(|glyph| {
+            let entry = entries.values().find(|entry| entry.id == glyph.id).unwrap();
+            (entry.key, glyph.advance_x)
+        })
+        .collect()
+}
+
+#[test]
+fn hex_box_for_missing_character() {
+    let Some(mut proc) = preprocessor(MissingGlyph::HexBox) else {
+        return;
+    };
+    proc.add_str(format!("a{UNCOVERED}b")).unwrap();
+    let glyphs = glyphs(&proc);
+    assert_eq!(glyphs.len(), 3);
+    let (key, advance) = glyphs[1];
+    assert_eq!(key.hex_box, Some(UNCOVERED));
+    assert_eq!(advance, font::hex_box_advance(UNCOVERED, 16.));
+    assert_eq!(glyphs[0].0.hex_box, None);
+}
+
+#[test]
+fn notdef_for_missing_character() {
+    let Some(mut proc) = preprocessor(MissingGlyph::Notdef) else {
+        return;
+    };
+    proc.add_str(format!("a{UNCOVERED}")).unwrap();
+    let (key, advance) = glyphs(&proc)[1];
+    assert_eq!(key.glyph_id, 0);
+    assert_eq!(advance, proc.fonts.primary().advance_width(0, 16.));
+}
+
+#[test]
+fn replacement_for_missing_character() {
+    let Some(mut proc) = preprocessor(MissingGlyph::Replacement('?')) else {
+        return;
+    };
+    proc.add_str(format!("a{UNCOVERED}")).unwrap();
+    let (key, _) = glyphs(&proc)[1];
+    let question = proc.fonts.primary().font_ref().charmap().map('?');
+    assert_eq!(key.glyph_id, question);
+    assert_ne!(question, 0);
+}
+
+#[test]
+fn skipped_missing_character_takes_no_space() {
+    let Some(mut proc) = preprocessor(MissingGlyph::Skip) else {
+        return;
+    };
+    proc.add_str(format!("a{UNCOVERED}b")).unwrap();
+    assert_eq!(glyphs(&proc).len(), 2);
+}
+
+#[test]
+fn format_characters_are_never_missing() {
+    let Some(mut proc) = preprocessor(MissingGlyph::HexBox) else {
+        return;
+    };
+    // a zero width joiner and a variation selector
+    proc.add_str("a\u{200D}b\u{FE0F}").unwrap();
+    assert!(glyphs(&proc).iter().all(|(key, _)| key.hex_box.is_none()));
+}
+
+#[test]
+fn tab_is_blank_space_four_spaces_wide() {
+    let Some(mut proc) = preprocessor(MissingGlyph::HexBox) else {
+        return;
+    };
+    proc.add_str("a\tb").unwrap();
+    let glyphs = glyphs(&proc);
+    assert_eq!(glyphs.len(), 3);
+    let font = proc.fonts.primary();
+    let space = font.font_ref().charmap().map(' ');
+    let (key, advance) = glyphs[1];
+    assert_eq!(key.glyph_id, space);
+    assert_eq!(advance, 4. * font.advance_width(space, 16.));
+}
+
+#[test]
+fn fallback_goes_through_the_stack_then_the_system() {
+    let (Some(mono), Some(sans)) = (load("DejaVu Sans Mono"), load("DejaVu Sans")) else {
+        eprintln!("skipping, DejaVu Sans Mono or DejaVu Sans isn't installed");
+        return;
+    };
+    // a character that the second font has but the first doesn't
+    let Some(ch) = ('\u{100}'..'\u{3000}').find(|&ch| !mono.covers(ch) && sans.covers(ch)) else {
+        eprintln!("skipping, DejaVu Sans Mono has everything DejaVu Sans has");
+        return;
+    };
+    let mut stack = FontStack::new(mono);
+    let second = stack.push(sans).unwrap();
+    assert_eq!(stack.resolve('a'), 0);
+    assert_eq!(stack.resolve(ch), second);
+
+    // a character neither of them have, which has to come from another system font
+    let Some(serif) = load("DejaVu Serif") else {
+        eprintln!("skipping, DejaVu Serif isn't installed");
+        return;
+    };
+    let Some(ch) = ('\u{100}'..'\u{FFFF}')
+        .find(|&ch| (0..=second).all(|id| !stack.font(id).covers(ch)) && serif.covers(ch))
+    else {
+        eprintln!("skipping, DejaVu Serif has nothing the others don't");
+        return;
+    };
+    assert_eq!(stack.resolve(ch), 0);
+    let mut stack = stack.with_fallback(font::Loader::system());
+    let found = stack.resolve(ch);
+    assert!(found > second);
+    assert!(stack.font(found).covers(ch));
+    // the font that was found is kept in the stack, and not searched for again
+    assert_eq!(stack.resolve(ch), found);
+    assert_eq!(stack.resolve(UNCOVERED), 0);
+}
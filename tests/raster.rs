@@ -0,0 +1,165 @@
+//! Fills glyphs in with the GPU raster pass and checks that their coverage is close to what swash
+//! rasterizes on the CPU from the same outlines.
+
+use std::iter;
+
+use titan_text::{
+    font::{self, GlyphMode, RasterOptions, Selector},
+    gpu::{
+        command::{Command, CommandList},
+        publish::create_atlas_texture,
+        raster::RasterPass,
+    },
+    preproc::{Atlas, AtlasKind, GlyphKey},
+};
+
+const ATLAS_SIZE: u32 = 256;
+const TEXT: &str = "agO&@";
+const POINT: f32 = 32.;
+/// the most that the coverage of a single pixel can be off by, out of 255
+const MAX_DIFFERENCE: u8 = 64;
+/// the most that the coverage of a glyph can be off by on average, out of 255
+const MAX_MEAN_DIFFERENCE: f32 = 4.;
+
+#[test]
+fn gpu_coverage_matches_swash() {
+    pollster::block_on(run());
+}
+
+async fn run() {
+    let Ok(font) = font::Loader::system().load_face(Selector::Family("DejaVu Sans")) else {
+        eprintln!("skipping, DejaVu Sans isn't installed");
+        return;
+    };
+    let Some((device, queue)) = load_gpu().await else {
+        eprintln!("skipping, no GPU adapter is available");
+        return;
+    };
+    // hinting changes the outline, which the GPU would then fill in differently from swash
+    let options = RasterOptions {
+        hinting: false,
+        ..RasterOptions::default()
+    };
+    let mut atlas = Atlas::new(ATLAS_SIZE, ATLAS_SIZE);
+    let mut expected = Vec::new();
+    for ch in TEXT.chars() {
+        let glyph_id = font.font_ref().charmap().map(ch);
+        let gpu = font
+            .load_glyph(glyph_id, POINT, GlyphMode::Gpu, &options, 0.)
+            .unwrap();
+        let cpu = font
+            .load_glyph(glyph_id, POINT, GlyphMode::Coverage, &options, 0.)
+            .unwrap();
+        let key = GlyphKey {
+            mode: GlyphMode::Gpu,
+            ..GlyphKey::new(0, glyph_id, POINT)
+        };
+        let id = atlas.store_glyph(key, gpu).unwrap();
+        expected.push((ch, id, cpu.image));
+    }
+
+    let view = atlas.as_atlas_view();
+    let mask_texture = create_atlas_texture(view, AtlasKind::Mask, &device);
+    let raster_pass = RasterPass::new(&device, view.entries.values(), &mask_texture);
+    let mut commands = CommandList::default();
+    raster_pass.push_buffers(&device, &mut commands);
+    commands.submit(&queue);
+    let coverage = read_first_layer(&mask_texture, &device, &queue).await;
+
+    for (ch, id, image) in expected {
+        let glyph = view.entries.values().find(|glyph| glyph.id == id).unwrap();
+        let gpu = glyph.glyph.image.placement;
+        let cpu = image.placement;
+        // swash leaves an empty column on each side of its images, so pixels are compared by
+        // where they are relative to the glyph's origin, with y pointing down
+        let gpu_coverage = |x: i32, y: i32| {
+            let (x, y) = (x - gpu.left, y + gpu.top);
+            let inside = x >= 0 && y >= 0 && x < gpu.width as i32 && y < gpu.height as i32;
+            match inside {
+                true => coverage[((glyph.y + y as u32) * ATLAS_SIZE + glyph.x + x as u32) as usize],
+                false => 0,
+            }
+        };
+        let cpu_coverage = |x: i32, y: i32| {
+            let (x, y) = (x - cpu.left, y + cpu.top);
+            let inside = x >= 0 && y >= 0 && x < cpu.width as i32 && y < cpu.height as i32;
+            match inside {
+                true => image.data[(y * cpu.width as i32 + x) as usize],
+                false => 0,
+            }
+        };
+        let (left, top) = (gpu.left.min(cpu.left), gpu.top.max(cpu.top));
+        let right = (gpu.left + gpu.width as i32).max(cpu.left + cpu.width as i32);
+        let bottom = (gpu.height as i32 - gpu.top).max(cpu.height as i32 - cpu.top);
+        let mut total = 0.;
+        for y in -top..bottom {
+            for x in left..right {
+                let (gpu, cpu) = (gpu_coverage(x, y), cpu_coverage(x, y));
+                let difference = gpu.abs_diff(cpu);
+                assert!(
+                    difference <= MAX_DIFFERENCE,
+                    "{ch:?} has {gpu} coverage at ({x}, {y}) where swash has {cpu}"
+                );
+                total += difference as f32;
+            }
+        }
+        let mean = total / ((right - left) * (bottom + top)) as f32;
+        assert!(
+            mean <= MAX_MEAN_DIFFERENCE,
+            "{ch:?} is {mean} off on average"
+        );
+    }
+}
+
+async fn load_gpu() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await?;
+    adapter.request_device(&Default::default(), None).await.ok()
+}
+
+/// Copies the first layer of an R8 atlas texture back from the GPU
+async fn read_first_layer(
+    texture: &wgpu::Texture,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Vec<u8> {
+    // rows of a texture copy have to be a multiple of 256 bytes apart, which ATLAS_SIZE is
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Test Atlas Buffer"),
+        size: (ATLAS_SIZE * ATLAS_SIZE) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Test Atlas Read Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(ATLAS_SIZE),
+                rows_per_image: Some(ATLAS_SIZE),
+            },
+        },
+        wgpu::Extent3d {
+            width: ATLAS_SIZE,
+            height: ATLAS_SIZE,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(iter::once(encoder.finish()));
+    let slice = buffer.slice(..);
+    let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    rx.receive().await.unwrap().unwrap();
+    let pixels = slice.get_mapped_range().to_vec();
+    pixels
+}
@@ -0,0 +1,298 @@
+//! Renders a known string through the layout, generation and render passes, and checks that
+//! every glyph's ink lands in the box that swash placed it in.
+
+use std::iter;
+
+use swash::{
+    scale::{Render, ScaleContext, Source, StrikeWith},
+    zeno::Format,
+};
+use titan_text::{
+    font,
+    gpu::{
+        command::{Command, CommandList},
+        generator::GenerationPass,
+        layout::LayoutPass,
+        publish::{
+            create_atlas_buffer, create_atlas_texture, publish_text, write_atlas_buffer,
+            write_atlas_texture,
+        },
+        render::RenderPass,
+    },
+    preproc::{self, Align, AtlasKind},
+};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 48;
+const TEXT: &str = "H g l";
+
+/// A box on the output texture, in whole pixels
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Bounds {
+    left: i32,
+    top: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Bounds {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.left && x < self.left + self.width && y >= self.top && y < self.top + self.height
+    }
+
+    /// The smallest box around the pixels of `self` that `ink` is true for
+    fn ink(&self, ink: impl Fn(i32, i32) -> bool) -> Option<Bounds> {
+        let mut found: Option<(i32, i32, i32, i32)> = None;
+        for y in self.top..self.top + self.height {
+            for x in self.left..self.left + self.width {
+                if ink(x, y) {
+                    found = Some(match found {
+                        None => (x, y, x, y),
+                        Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                    });
+                }
+            }
+        }
+        found.map(|(x0, y0, x1, y1)| Bounds {
+            left: x0,
+            top: y0,
+            width: x1 - x0 + 1,
+            height: y1 - y0 + 1,
+        })
+    }
+}
+
+/// Where swash puts a glyph's image on the output, and the part of it that has any coverage
+struct Expected {
+    placement: Bounds,
+    ink: Bounds,
+}
+
+#[test]
+fn glyph_quads_match_swash_placements() {
+    pollster::block_on(run(Align::Start));
+}
+
+#[test]
+fn right_aligned_glyphs_match_swash_placements() {
+    pollster::block_on(run(Align::Right));
+}
+
+#[test]
+fn centered_glyphs_match_swash_placements() {
+    pollster::block_on(run(Align::Center));
+}
+
+async fn run(align: Align) {
+    let mut loader = font::Loader::system();
+    let query = fontdb::Query {
+        families: &[
+            fontdb::Family::Name("DejaVu Sans"),
+            fontdb::Family::SansSerif,
+        ],
+        weight: fontdb::Weight::NORMAL,
+        stretch: fontdb::Stretch::Normal,
+        style: fontdb::Style::Normal,
+    };
+    let Ok(font) = loader.load_font(&query) else {
+        eprintln!("skipping, no sans serif font is installed");
+        return;
+    };
+    let Some((device, queue)) = load_gpu().await else {
+        eprintln!("skipping, no GPU adapter is available");
+        return;
+    };
+    let mut proc = preproc::Preprocessor::new(
+        font::FontStack::new(font.font),
+        preproc::Atlas::new(256, 256),
+        24.,
+    );
+    proc.align = align;
+    proc.wrap_width = WIDTH as f32;
+    proc.add_str(TEXT).unwrap();
+
+    let atlas = proc.atlas.as_atlas_view();
+    let mask_texture = create_atlas_texture(atlas, AtlasKind::Mask, &device);
+    write_atlas_texture(atlas, AtlasKind::Mask, &mask_texture, &queue);
+    let color_texture = create_atlas_texture(atlas, AtlasKind::Color, &device);
+    write_atlas_texture(atlas, AtlasKind::Color, &color_texture, &queue);
+    let glyph_data = create_atlas_buffer(atlas, &device);
+    write_atlas_buffer(atlas, &glyph_data, &queue);
+    let text = publish_text(&proc.text, &device, &queue);
+
+    let layout_pass = LayoutPass::new(&device, &text, &glyph_data);
+    let generate_pass = GenerationPass::new(
+        &device,
+        &text,
+        &glyph_data,
+        &layout_pass.layout_buffer,
+        atlas,
+    );
+    let mut commands = CommandList::default();
+    layout_pass.push_buffers(&device, &mut commands);
+    generate_pass.push_buffers(&device, &mut commands);
+    commands.submit(&queue);
+
+    let output = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Test Output Texture"),
+        size: wgpu::Extent3d {
+            width: WIDTH,
+            height: HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let render_pass = RenderPass::new(
+        &device,
+        &output,
+        &generate_pass.vertex_buffer,
+        &generate_pass.index_buffer,
+        &mask_texture,
+        &color_texture,
+        text.glyphs * 6,
+    );
+    render_pass.render(&device, &queue);
+    let pixels = read_texture(&output, &device, &queue).await;
+    // the text is white over a dark blue background, so red is only ever ink
+    let ink = |x: i32, y: i32| {
+        let inside = x >= 0 && y >= 0 && x < WIDTH as i32 && y < HEIGHT as i32;
+        inside && pixels[((y as u32 * WIDTH + x as u32) * 4) as usize] > 0
+    };
+
+    let expected = swash_bounds(&proc);
+    assert_eq!(
+        expected.len(),
+        3,
+        "every letter of {TEXT:?} should have an image"
+    );
+    for glyph in &expected {
+        // the ink found around where the glyph should be, with a margin to catch it being off
+        let around = Bounds {
+            left: glyph.placement.left - 2,
+            top: glyph.placement.top - 2,
+            width: glyph.placement.width + 4,
+            height: glyph.placement.height + 4,
+        };
+        assert_eq!(around.ink(ink), Some(glyph.ink));
+    }
+    for y in 0..HEIGHT as i32 {
+        for x in 0..WIDTH as i32 {
+            assert!(
+                !ink(x, y) || expected.iter().any(|glyph| glyph.placement.contains(x, y)),
+                "ink at ({x}, {y}) is outside of every glyph"
+            );
+        }
+    }
+}
+
+/// Where each glyph of the only line should be drawn, from the pen position that the shaper
+/// gives it and the placement of the image that swash renders for it. Aligned lines are moved by
+/// whole pixels, so that glyphs keep the subpixel offset they were rasterized at.
+fn swash_bounds(proc: &preproc::Preprocessor) -> Vec<Expected> {
+    let line = &proc.text.lines[0];
+    let entries = proc.atlas.as_atlas_view().entries;
+    let mut context = ScaleContext::new();
+    let space = line.width - line.glyphs.iter().map(|glyph| glyph.advance_x).sum::<f32>();
+    let mut pen = match proc.align {
+        Align::Right => space.round(),
+        Align::Center => (space / 2.).floor(),
+        _ => 0.,
+    };
+    let mut bounds = Vec::new();
+    for glyph in &line.glyphs {
+        let key = entries
+            .values()
+            .find(|entry| entry.id == glyph.id)
+            .expect("every glyph of the line is in the atlas")
+            .key;
+        let font = proc.fonts.font(key.font_id);
+        let mut scaler = context
+            .builder(font.font_ref())
+            .hint(true)
+            .size(key.size())
+            .normalized_coords(font.coords())
+            .build();
+        let image = Render::new(&[
+            Source::ColorOutline(0),
+            Source::ColorBitmap(StrikeWith::BestFit),
+            Source::Outline,
+        ])
+        .format(Format::Alpha)
+        .render(&mut scaler, key.glyph_id);
+        let origin_x = (pen + glyph.offset_x).floor() as i32;
+        let origin_y = (line.baseline() - glyph.offset_y).floor() as i32;
+        pen += glyph.advance_x;
+        let Some(image) = image else {
+            continue;
+        };
+        let placement = Bounds {
+            left: origin_x + image.placement.left,
+            top: origin_y - image.placement.top,
+            width: image.placement.width as i32,
+            height: image.placement.height as i32,
+        };
+        let coverage = |x: i32, y: i32| {
+            let (x, y) = (x - placement.left, y - placement.top);
+            image.data[(y * placement.width + x) as usize] > 0
+        };
+        if let Some(ink) = placement.ink(coverage) {
+            bounds.push(Expected { placement, ink });
+        }
+    }
+    bounds
+}
+
+async fn load_gpu() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await?;
+    adapter.request_device(&Default::default(), None).await.ok()
+}
+
+/// Copies the RGBA pixels of `texture` back from the GPU, row by row from the top
+async fn read_texture(
+    texture: &wgpu::Texture,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Vec<u8> {
+    // rows of a texture copy have to be a multiple of 256 bytes apart, which WIDTH is
+    let bytes_per_row = 4 * texture.width();
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Test Output Buffer"),
+        size: (bytes_per_row * texture.height()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Test Output Read Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(texture.height()),
+            },
+        },
+        texture.size(),
+    );
+    queue.submit(iter::once(encoder.finish()));
+    let slice = buffer.slice(..);
+    let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    rx.receive().await.unwrap().unwrap();
+    let pixels = slice.get_mapped_range().to_vec();
+    pixels
+}
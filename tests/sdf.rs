@@ -0,0 +1,251 @@
+//! Draws one distance field glyph at two sizes, and checks that both come from a single atlas
+//! entry that is scaled to land where swash puts the glyph at each size.
+
+use std::iter;
+
+use swash::{
+    scale::{Render, ScaleContext, Source},
+    zeno::Format,
+};
+use titan_text::{
+    font::{self, GlyphMode, Selector},
+    gpu::{
+        command::{Command, CommandList},
+        generator::GenerationPass,
+        layout::LayoutPass,
+        publish::{
+            create_atlas_buffer, create_atlas_texture, publish_text, write_atlas_buffer,
+            write_atlas_texture,
+        },
+        render::RenderPass,
+    },
+    preproc::{self, AtlasKind},
+};
+
+const TUFFY: &[u8] = include_bytes!("fonts/Tuffy.ttf");
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 128;
+const SIZES: [f32; 2] = [16., 64.];
+
+/// A box on the output texture, in whole pixels, from `left` and `top` up to but not including
+/// `right` and `bottom`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Bounds {
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+}
+
+#[test]
+fn one_field_is_drawn_at_every_size() {
+    let proc = lay_out();
+    let entries = proc.atlas.as_atlas_view().entries;
+    assert_eq!(entries.len(), 1, "both sizes should share one atlas entry");
+    let entry = entries.values().next().unwrap();
+    assert_eq!(entry.key.size(), GlyphMode::FIELD_SIZE);
+    for (line, point) in proc.text.lines.iter().zip(SIZES) {
+        assert_eq!(line.glyphs.len(), 1);
+        assert_eq!(line.glyphs[0].id, entry.id);
+        assert_eq!(line.glyphs[0].scale, point / GlyphMode::FIELD_SIZE);
+    }
+}
+
+#[test]
+fn scaled_fields_cover_the_glyph_at_each_size() {
+    pollster::block_on(run());
+}
+
+fn lay_out() -> preproc::Preprocessor {
+    let font = font::Loader::from_static([TUFFY])
+        .unwrap()
+        .load_face(Selector::Family("Tuffy"))
+        .unwrap();
+    let mut proc = preproc::Preprocessor::new(
+        font::FontStack::new(font),
+        preproc::Atlas::new(256, 256),
+        SIZES[0],
+    );
+    proc.mode = GlyphMode::Sdf { spread: 4 };
+    for point in SIZES {
+        proc.point = point;
+        proc.add_str("H").unwrap();
+    }
+    proc
+}
+
+async fn run() {
+    let Some((device, queue)) = load_gpu().await else {
+        eprintln!("skipping, no GPU adapter is available");
+        return;
+    };
+    let proc = lay_out();
+    let atlas = proc.atlas.as_atlas_view();
+    let mask_texture = create_atlas_texture(atlas, AtlasKind::Mask, &device);
+    write_atlas_texture(atlas, AtlasKind::Mask, &mask_texture, &queue);
+    let color_texture = create_atlas_texture(atlas, AtlasKind::Color, &device);
+    write_atlas_texture(atlas, AtlasKind::Color, &color_texture, &queue);
+    let glyph_data = create_atlas_buffer(atlas, &device);
+    write_atlas_buffer(atlas, &glyph_data, &queue);
+    let text = publish_text(&proc.text, &device, &queue);
+
+    let layout_pass = LayoutPass::new(&device, &text, &glyph_data);
+    let generate_pass = GenerationPass::new(
+        &device,
+        &text,
+        &glyph_data,
+        &layout_pass.layout_buffer,
+        atlas,
+    );
+    let mut commands = CommandList::default();
+    layout_pass.push_buffers(&device, &mut commands);
+    generate_pass.push_buffers(&device, &mut commands);
+    commands.submit(&queue);
+
+    let output = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Test Output Texture"),
+        size: wgpu::Extent3d {
+            width: WIDTH,
+            height: HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let render_pass = RenderPass::new(
+        &device,
+        &output,
+        &generate_pass.vertex_buffer,
+        &generate_pass.index_buffer,
+        &mask_texture,
+        &color_texture,
+        text.glyphs * 6,
+    );
+    render_pass.render(&device, &queue);
+    let pixels = read_texture(&output, &device, &queue).await;
+    // the text is white over a dark blue background, so red is only ever ink
+    let ink = |x: i32, y: i32| {
+        let inside = x >= 0 && y >= 0 && x < WIDTH as i32 && y < HEIGHT as i32;
+        inside && pixels[((y as u32 * WIDTH + x as u32) * 4) as usize] > 0
+    };
+
+    let font = proc.fonts.font(0);
+    let mut context = ScaleContext::new();
+    for (line, point) in proc.text.lines.iter().zip(SIZES) {
+        let glyph_id = font.font_ref().charmap().map('H');
+        let mut scaler = context
+            .builder(font.font_ref())
+            .hint(false)
+            .size(point)
+            .build();
+        let image = Render::new(&[Source::Outline])
+            .format(Format::Alpha)
+            .render(&mut scaler, glyph_id)
+            .unwrap();
+        let baseline = line.baseline().floor() as i32;
+        let coverage = |x: i32, y: i32| {
+            let (x, y) = (x - image.placement.left, y + image.placement.top);
+            let inside = x >= 0
+                && y >= 0
+                && x < image.placement.width as i32
+                && y < image.placement.height as i32;
+            inside && image.data[(y * image.placement.width as i32 + x) as usize] > 0
+        };
+        // pixels relative to the pen, over the rows of the line
+        let rows = line.top.floor() as i32 - baseline..(line.top + line.height()) as i32 - baseline;
+        let expected = bounds(0..WIDTH as i32, rows.clone(), coverage).unwrap();
+        let drawn = bounds(0..WIDTH as i32, rows, |x, y| ink(x, y + baseline)).unwrap();
+        // the field's edge fades out over a pixel, which can reach one pixel further out
+        for (drawn, expected) in [
+            (drawn.left, expected.left),
+            (drawn.top, expected.top),
+            (drawn.right, expected.right),
+            (drawn.bottom, expected.bottom),
+        ] {
+            assert!(
+                (drawn - expected).abs() <= 1,
+                "at {point}pt the field covers {drawn:?} instead of {expected:?}"
+            );
+        }
+    }
+}
+
+/// The smallest box around the pixels in `xs` and `ys` that `ink` is true for
+fn bounds(
+    xs: std::ops::Range<i32>,
+    ys: std::ops::Range<i32>,
+    ink: impl Fn(i32, i32) -> bool,
+) -> Option<Bounds> {
+    let mut found: Option<Bounds> = None;
+    for y in ys {
+        for x in xs.clone() {
+            if ink(x, y) {
+                let found = found.get_or_insert(Bounds {
+                    left: x,
+                    top: y,
+                    right: x + 1,
+                    bottom: y + 1,
+                });
+                found.left = found.left.min(x);
+                found.top = found.top.min(y);
+                found.right = found.right.max(x + 1);
+                found.bottom = found.bottom.max(y + 1);
+            }
+        }
+    }
+    found
+}
+
+async fn load_gpu() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await?;
+    adapter.request_device(&Default::default(), None).await.ok()
+}
+
+/// Copies the RGBA pixels of `texture` back from the GPU, row by row from the top
+async fn read_texture(
+    texture: &wgpu::Texture,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Vec<u8> {
+    // rows of a texture copy have to be a multiple of 256 bytes apart, which WIDTH is
+    let bytes_per_row = 4 * texture.width();
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Test Output Buffer"),
+        size: (bytes_per_row * texture.height()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Test Output Read Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(texture.height()),
+            },
+        },
+        texture.size(),
+    );
+    queue.submit(iter::once(encoder.finish()));
+    let slice = buffer.slice(..);
+    let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    rx.receive().await.unwrap().unwrap();
+    let pixels = slice.get_mapped_range().to_vec();
+    pixels
+}